@@ -0,0 +1,113 @@
+//! Throughput of tiny items through an item-compressed archive.
+//!
+//! The `fresh_*` benchmarks reproduce the old behaviour, of building a new zstd context per item,
+//! for comparison against the writer and reader, which reuse their contexts.
+
+use std::hint::black_box;
+use std::io;
+use std::io::{Read, Write};
+
+use archiv::{Compress, CompressOptions, ExpandOptions};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const ITEM_SIZE: usize = 100;
+const ITEMS: usize = 10_000;
+
+fn items() -> Vec<Vec<u8>> {
+    (0..ITEMS)
+        .map(|i| {
+            format!("{{\"id\":{i},\"name\":\"item number {i}\",\"tags\":[\"a\",\"b\"]}}")
+                .into_bytes()
+                .into_iter()
+                .cycle()
+                .take(ITEM_SIZE)
+                .collect()
+        })
+        .collect()
+}
+
+fn archive(items: &[Vec<u8>]) -> Vec<u8> {
+    let mut archiv = CompressOptions::default()
+        .item_compress(Vec::new())
+        .expect("in memory");
+    for item in items {
+        archiv.write_item(item).expect("in memory");
+    }
+    archiv.finish().expect("in memory")
+}
+
+fn write(c: &mut Criterion) {
+    let items = items();
+    let mut group = c.benchmark_group("write");
+    group.throughput(Throughput::Bytes((ITEMS * ITEM_SIZE) as u64));
+
+    group.bench_function("fresh_encoder", |b| {
+        b.iter(|| {
+            let mut out = Vec::new();
+            for item in &items {
+                let mut buf = Vec::with_capacity(item.len() / 4 + 30);
+                let mut writer = zstd::Encoder::new(&mut buf, 0).expect("in memory");
+                writer
+                    .set_pledged_src_size(Some(item.len() as u64))
+                    .expect("in memory");
+                writer.include_contentsize(true).expect("in memory");
+                writer.write_all(item).expect("in memory");
+                writer.finish().expect("in memory");
+                out.extend_from_slice(&(buf.len() as u64).to_le_bytes());
+                out.extend_from_slice(&buf);
+            }
+            black_box(out)
+        })
+    });
+
+    group.bench_function("compress_item", |b| b.iter(|| black_box(archive(&items))));
+    group.finish();
+}
+
+fn read(c: &mut Criterion) {
+    let items = items();
+    let file = archive(&items);
+    let mut group = c.benchmark_group("read");
+    group.throughput(Throughput::Bytes((ITEMS * ITEM_SIZE) as u64));
+
+    group.bench_function("fresh_decoder", |b| {
+        b.iter(|| {
+            let mut file = &file[8..];
+            let mut buf = Vec::with_capacity(ITEM_SIZE);
+            loop {
+                let mut len = [0u8; 8];
+                file.read_exact(&mut len).expect("in memory");
+                let len = u64::from_le_bytes(len);
+                if len >= 0xf000_0000_0000_0000 {
+                    break;
+                }
+                let (frame, rest) = file.split_at(len as usize);
+                buf.clear();
+                zstd::Decoder::with_buffer(frame)
+                    .expect("in memory")
+                    .read_to_end(&mut buf)
+                    .expect("in memory");
+                black_box(&buf);
+                file = rest;
+            }
+        })
+    });
+
+    group.bench_function("expand_item", |b| {
+        b.iter(|| {
+            let mut archiv = ExpandOptions::default()
+                .stream(io::Cursor::new(&file))
+                .expect("in memory");
+            let mut buf = Vec::with_capacity(ITEM_SIZE);
+            while let Some(mut item) = archiv.next_item().expect("in memory") {
+                buf.clear();
+                item.read_to_end(&mut buf).expect("in memory");
+                black_box(&buf);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, write, read);
+criterion_main!(benches);
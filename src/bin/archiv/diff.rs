@@ -0,0 +1,69 @@
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use archiv::{Expand, ExpandOptions};
+
+/// Compare the items of two archivs, in order, printing any differences.
+/// Returns whether the items are the same.
+pub fn diff(a: &Path, b: &Path) -> Result<bool> {
+    let mut left = Side::open(a)?;
+    let mut right = Side::open(b)?;
+    let mut stdout = io::stdout().lock();
+    let mut same = true;
+    for index in 0u64.. {
+        let l = left.next().with_context(|| anyhow!("{a:?}"))?;
+        let r = right.next().with_context(|| anyhow!("{b:?}"))?;
+        let status = match (l, r) {
+            (false, false) => break,
+            (true, false) => "removed",
+            (false, true) => "added",
+            (true, true) if left.buf != right.buf => "changed",
+            (true, true) => continue,
+        };
+        same = false;
+        writeln!(stdout, "{status}\t{index}")?;
+    }
+    stdout.flush()?;
+    Ok(same)
+}
+
+struct Side {
+    archiv: Box<dyn Expand>,
+    buf: Vec<u8>,
+    finished: bool,
+}
+
+impl Side {
+    fn open(file: &Path) -> Result<Side> {
+        let f = fs::File::open(file).with_context(|| anyhow!("{file:?}"))?;
+        let archiv = ExpandOptions::default()
+            .stream(io::BufReader::new(f))
+            .with_context(|| anyhow!("{file:?}"))?;
+        Ok(Side {
+            archiv,
+            buf: Vec::new(),
+            finished: false,
+        })
+    }
+
+    /// read the next item into `buf`, returning `false` at (and after) the end of the archiv
+    fn next(&mut self) -> Result<bool> {
+        self.buf.clear();
+        if self.finished {
+            return Ok(false);
+        }
+        match self.archiv.next_item()? {
+            Some(mut item) => {
+                item.read_to_end(&mut self.buf)?;
+                Ok(true)
+            }
+            None => {
+                self.finished = true;
+                Ok(false)
+            }
+        }
+    }
+}
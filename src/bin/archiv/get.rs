@@ -0,0 +1,62 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use archiv::{Error, ExpandOptions};
+
+pub fn get(file: &Path, index: Option<u64>, offset: Option<u64>) -> Result<()> {
+    let opts = ExpandOptions::default();
+    let mut f = io::BufReader::new(fs::File::open(file).with_context(|| anyhow!("{file:?}"))?);
+
+    let offset = match (index, offset) {
+        (_, Some(offset)) => offset,
+        (Some(index), None) => match opts.raw_seekable(&mut f) {
+            Ok(mut archiv) => {
+                for _ in 0..index {
+                    if !archiv.skip_raw()? {
+                        bail!("archive only has {} items", archiv.index());
+                    }
+                }
+                archiv.offset()
+            }
+            Err(Error::NotSeekable) => {
+                return scan(file, &format!("index {index}"), |i, _| i == index)
+            }
+            Err(e) => return Err(e.into()),
+        },
+        (None, None) => bail!("an index or offset is required"),
+    };
+
+    let mut item = match opts.item_at(&mut f, offset) {
+        Ok(item) => item,
+        Err(Error::NotSeekable) => {
+            return scan(file, &format!("offset {offset}"), |_, o| o == offset)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut stdout = io::stdout().lock();
+    io::copy(&mut item, &mut stdout)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// stream-compressed archives have to be read from the start
+fn scan(file: &Path, what: &str, wanted: impl Fn(u64, u64) -> bool) -> Result<()> {
+    let f = io::BufReader::new(fs::File::open(file)?);
+    let mut archiv = ExpandOptions::default().raw(f)?;
+    let mut raw = Vec::with_capacity(4096);
+    while let Some(item) = archiv.next_raw(&mut raw)? {
+        if !wanted(item.index, item.offset) {
+            continue;
+        }
+        let mut out = Vec::with_capacity(raw.len());
+        archiv.expand_raw(&raw, &mut out)?;
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&out)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+    bail!("no item at {what}, in {} items", archiv.index());
+}
@@ -0,0 +1,125 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use archiv::{Codec, ExpandOptions, Layout, RawItem};
+use rayon::prelude::*;
+use regex::bytes::{Regex, RegexBuilder};
+
+// item-compressed frames are decompressed in parallel, this many (stored) bytes at a time
+const BATCH_BYTES: usize = 16 * 1024 * 1024;
+
+/// Print the index and offset of each item matching the pattern, and, optionally, the item.
+/// Returns whether anything matched.
+pub fn grep(pattern: &str, files: &[PathBuf], ignore_case: bool, print: bool) -> Result<bool> {
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()?;
+    let opts = ExpandOptions::default();
+    let mut out = Output {
+        stdout: io::stdout().lock(),
+        print,
+        matched: false,
+    };
+    for file in files {
+        let f = fs::File::open(file).with_context(|| anyhow!("{file:?}"))?;
+        let mut archiv = opts
+            .raw(io::BufReader::new(f))
+            .with_context(|| anyhow!("{file:?}"))?;
+        let prefix = match files.len() {
+            1 => String::new(),
+            _ => format!("{}\t", file.display()),
+        };
+
+        let mut raw = Vec::with_capacity(4096);
+        let codec = match (archiv.layout(), archiv.codec()) {
+            (Layout::ItemCompressed, Some(codec)) => codec,
+            _ => {
+                // nothing to decompress, so nothing to parallelise
+                while let Some(item) = archiv.next_raw(&mut raw)? {
+                    if regex.is_match(&raw) {
+                        out.item(&prefix, &item, &raw)?;
+                    }
+                }
+                continue;
+            }
+        };
+
+        let mut batch = Vec::new();
+        let mut batch_bytes = 0;
+        loop {
+            let item = archiv.next_raw(&mut raw)?;
+            if let Some(item) = item {
+                batch_bytes += raw.len();
+                batch.push((item, mem::take(&mut raw)));
+                if batch_bytes < BATCH_BYTES {
+                    continue;
+                }
+            }
+            for (item, content) in search(
+                &opts,
+                codec,
+                archiv.stored_items(),
+                &regex,
+                mem::take(&mut batch),
+            )? {
+                out.item(&prefix, &item, &content)?;
+            }
+            batch_bytes = 0;
+            if item.is_none() {
+                break;
+            }
+        }
+    }
+    out.stdout.flush()?;
+    Ok(out.matched)
+}
+
+/// decompress and match a batch of frames, returning the matching items in order
+fn search(
+    opts: &ExpandOptions,
+    codec: Codec,
+    stored_items: bool,
+    regex: &Regex,
+    batch: Vec<(RawItem, Vec<u8>)>,
+) -> Result<Vec<(RawItem, Vec<u8>)>> {
+    let found = batch
+        .into_par_iter()
+        .map_init(
+            || opts.frame_expand(codec, stored_items),
+            |expand, (item, frame)| -> Result<_> {
+                let expand = expand
+                    .as_mut()
+                    .map_err(|e| anyhow!("creating a decompressor: {e}"))?;
+                let mut content = Vec::new();
+                expand
+                    .expand(&frame, &mut content)
+                    .with_context(|| anyhow!("item {} at offset {}", item.index, item.offset))?;
+                Ok(regex.is_match(&content).then_some((item, content)))
+            },
+        )
+        .collect::<Result<Vec<_>>>()?;
+    Ok(found.into_iter().flatten().collect())
+}
+
+struct Output<W> {
+    stdout: W,
+    print: bool,
+    matched: bool,
+}
+
+impl<W: Write> Output<W> {
+    fn item(&mut self, prefix: &str, item: &RawItem, content: &[u8]) -> Result<()> {
+        self.matched = true;
+        write!(self.stdout, "{prefix}{}\t{}", item.index, item.offset)?;
+        if self.print {
+            self.stdout.write_all(b"\t")?;
+            self.stdout.write_all(content)?;
+        }
+        self.stdout.write_all(b"\n")?;
+        Ok(())
+    }
+}
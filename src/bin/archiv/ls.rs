@@ -0,0 +1,34 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use archiv::ExpandOptions;
+
+pub fn ls(files: &[PathBuf]) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    let mut raw = Vec::with_capacity(4096);
+    for file in files {
+        let f = fs::File::open(file).with_context(|| anyhow!("{file:?}"))?;
+        let mut archiv = ExpandOptions::default()
+            .raw(io::BufReader::new(f))
+            .with_context(|| anyhow!("{file:?}"))?;
+        let prefix = match files.len() {
+            1 => String::new(),
+            _ => format!("{}\t", file.display()),
+        };
+        while let Some(item) = archiv.next_raw(&mut raw)? {
+            let content_len = match item.content_len {
+                Some(len) => len.to_string(),
+                None => "-".to_string(),
+            };
+            writeln!(
+                stdout,
+                "{prefix}{}\t{}\t{}\t{content_len}",
+                item.index, item.offset, item.stored_len
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -1,12 +1,21 @@
+mod diff;
+mod get;
+mod grep;
+mod ls;
+mod output;
+mod pack;
+mod split;
+mod stats;
 mod train;
+mod verify;
 
-use std::io::Read;
+use std::io;
 use std::path::PathBuf;
-use std::{fs, io};
+use std::process::ExitCode;
 
-use anyhow::{anyhow, Context, Result};
-use archiv::{Compress, CompressOptions, ExpandOptions};
-use clap::{Parser, Subcommand};
+use anyhow::Result;
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -17,20 +26,172 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Dump the contents of files into a single archiv, written to stdout
+    /// Dump the contents of files, or records read from files or stdin, into a single archiv
+    ///
+    /// The archiv is written to stdout, or atomically to --output.
     Pack {
-        #[arg(required = true)]
+        /// Files (or directories, with --recursive) to pack, each as an item, or split into items
+        /// with --from-*; '-' for stdin
         files: Vec<PathBuf>,
+
+        #[command(flatten)]
+        walk: pack::WalkArgs,
+
+        #[command(flatten)]
+        input: pack::InputArgs,
+
+        #[command(flatten)]
+        compress: pack::CompressArgs,
+
+        /// Write the archiv to this path, which only appears once the archiv is complete
+        #[arg(short, long)]
+        output: Option<PathBuf>,
     },
 
+    /// Summarise the contents of archiv(s), without decompressing items where possible
     Stats {
+        /// Archivs to summarise
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Print the summaries as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Decompress every item to measure it, not just those which don't declare their size
+        #[arg(long)]
+        decompress: bool,
+    },
+
+    /// List the items in archiv(s)
+    ///
+    /// Items are listed one per line: index, offset, stored length, and decompressed
+    /// length ('-' if the frame doesn't declare it), tab separated. The file name is prefixed
+    /// when listing multiple archivs.
+    Ls {
+        /// Archivs to list
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Print the index and offset of each item matching a regex
+    ///
+    /// Matches are printed tab separated, prefixed by the file name when searching multiple
+    /// archivs. Exits non-zero if nothing matched.
+    Grep {
+        /// Regex to search for, within each item
+        pattern: String,
+
+        /// Archivs to search
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Match case-insensitively
+        #[arg(short, long)]
+        ignore_case: bool,
+
+        /// Print the matching item, after another tab, and before the newline
+        #[arg(short, long)]
+        print: bool,
+    },
+
+    /// Write a single item to stdout, found by its index or offset
+    #[command(group(ArgGroup::new("at").required(true).args(["index", "offset"])))]
+    Get {
+        /// Archiv to read from
+        file: PathBuf,
+
+        /// The item's position in the archiv, starting from zero
+        #[arg(long)]
+        index: Option<u64>,
+
+        /// The item's offset in bytes, as printed by `ls` or returned by `write_item`
+        #[arg(long)]
+        offset: Option<u64>,
+    },
+
+    /// Split an archiv into numbered parts of the same kind
+    ///
+    /// Parts are named PREFIX.0000.archiv onwards. Item-compressed frames are copied as they are.
+    #[command(group(ArgGroup::new("limit").required(true).multiple(true).args(["items", "bytes"])))]
+    Split {
+        /// Archiv to split
+        file: PathBuf,
+
+        /// Start a new part after this many items
+        #[arg(long)]
+        items: Option<u64>,
+
+        /// Start a new part before the items, as stored, would exceed this many bytes
+        /// (before any stream compression)
+        #[arg(long)]
+        bytes: Option<u64>,
+
+        /// Name the parts after this, instead of the input file without its extension
+        #[arg(long)]
+        prefix: Option<PathBuf>,
+
+        /// Dictionary for reading and writing stream-compressed archivs
+        #[arg(long)]
+        dict: Option<PathBuf>,
+
+        /// zstd compression level, for stream-compressed archivs
+        #[arg(short, long)]
+        level: Option<i32>,
+    },
+
+    /// Concatenate the items of archivs into one
+    ///
+    /// The output is the same kind as the first input, unless specified. Item-compressed frames
+    /// are copied as they are, where they use the same dictionary.
+    Merge {
+        /// Archivs to merge, in order
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Path to write the merged archiv to
+        #[arg(short, long)]
+        output: PathBuf,
+
+        #[command(flatten)]
+        compress: pack::CompressArgs,
+    },
+
+    /// Compare the items of two archivs, whatever their kind or compression
+    ///
+    /// Prints 'changed', 'removed' or 'added' and the index of each differing item.
+    /// Exits non-zero if any differ.
+    Diff {
+        /// The original archiv
+        a: PathBuf,
+
+        /// The archiv to compare it with
+        b: PathBuf,
+    },
+
+    /// Check that archiv(s) are complete and every item can be read; exits non-zero on failure
+    Verify {
+        /// Archivs to check
         #[arg(required = true)]
         files: Vec<PathBuf>,
     },
 
+    /// Print a shell completion script to stdout
+    Completions {
+        /// The shell to generate the script for
+        shell: Shell,
+    },
+
+    /// Generate man pages, for archiv and each subcommand, or print archiv's to stdout
+    Man {
+        /// Directory to write archiv.1, archiv-pack.1, etc. to
+        #[arg(short, long)]
+        out_dir: Option<PathBuf>,
+    },
+
     /// Build a dictionary from documents 'randomly' selected from source archive(s)
     Train {
-        /// archivs to read source documents from
+        /// Archivs to read source documents from
         #[arg(required = true)]
         sources: Vec<PathBuf>,
 
@@ -44,59 +205,87 @@ enum Commands {
     },
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let cli: Cli = Cli::parse();
     match cli.command {
-        Commands::Pack { files } => pack(&files)?,
-        Commands::Stats { files } => stats(&files)?,
+        Commands::Pack {
+            files,
+            walk,
+            input,
+            compress,
+            output,
+        } => pack::pack(&files, &walk, &input, &compress, output.as_deref())?,
+        Commands::Stats {
+            files,
+            json,
+            decompress,
+        } => stats::stats(&files, json, decompress)?,
+        Commands::Ls { files } => ls::ls(&files)?,
+        Commands::Grep {
+            pattern,
+            files,
+            ignore_case,
+            print,
+        } => {
+            if !grep::grep(&pattern, &files, ignore_case, print)? {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Get {
+            file,
+            index,
+            offset,
+        } => get::get(&file, index, offset)?,
+        Commands::Split {
+            file,
+            items,
+            bytes,
+            prefix,
+            dict,
+            level,
+        } => {
+            let compress = pack::CompressArgs {
+                items: false,
+                stream: false,
+                codec: None,
+                level,
+                dict,
+                trailer: false,
+                stored_items: false,
+            };
+            let limits = split::Limits { items, bytes };
+            split::split(&file, limits, prefix.as_deref(), &compress)?;
+        }
+        Commands::Merge {
+            files,
+            output,
+            compress,
+        } => split::merge(&files, &output, &compress)?,
+        Commands::Diff { a, b } => {
+            if !diff::diff(&a, &b)? {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Verify { files } => {
+            if !verify::verify(&files)? {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "archiv", &mut io::stdout());
+        }
+        Commands::Man { out_dir } => match out_dir {
+            Some(dir) => clap_mangen::generate_to(Cli::command(), dir)?,
+            None => clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())?,
+        },
         Commands::Train {
             sources,
             out,
             limit,
         } => {
             let dict = train::train(&sources, limit)?;
-            fs::write(out, dict)?;
+            output::write(&out, &dict)?;
         }
     }
-    Ok(())
-}
-
-fn pack(files: &[PathBuf]) -> Result<()> {
-    let stdout = io::stdout().lock();
-    let opts = CompressOptions::default();
-    let mut archiv = opts.stream_compress(stdout)?;
-    let mut buf = Vec::with_capacity(4096);
-    for file in files {
-        buf.clear();
-        let mut file = fs::File::open(file).with_context(|| anyhow!("{file:?}"))?;
-        file.read_to_end(&mut buf)?;
-        archiv.write_item(&buf)?;
-    }
-    let _ = archiv.finish()?;
-    Ok(())
-}
-
-fn stats(files: &[PathBuf]) -> Result<()> {
-    for file in files {
-        print!("{}: ", file.display());
-        let file = fs::File::open(file).with_context(|| anyhow!("{file:?}"))?;
-        let file = io::BufReader::new(file);
-        let mut file = ExpandOptions::default().stream(file)?;
-        let mut count = 0u64;
-        let mut bytes = 0u64;
-        let mut buf = Vec::with_capacity(4096);
-        while let Some(mut item) = file.next_item()? {
-            buf.clear();
-            item.read_to_end(&mut buf)?;
-            bytes += u64::try_from(buf.len())?;
-            count += 1;
-        }
-        println!(
-            "{} items, {} bytes, {} mean size",
-            count,
-            bytes,
-            bytes / count
-        );
-    }
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,53 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use tempfile::NamedTempFile;
+
+/// A file which only appears under its final name once it has been completely written
+pub type Output = io::BufWriter<NamedTempFile>;
+
+/// Start writing to a temporary file alongside `path`
+pub fn create(path: &Path) -> Result<Output> {
+    let dir = parent(path);
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(".archiv-").suffix(".tmp");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // as `File::create` would, subject to the umask
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+    let file = builder
+        .tempfile_in(dir)
+        .with_context(|| anyhow!("creating a temporary file in {dir:?}"))?;
+    Ok(io::BufWriter::new(file))
+}
+
+/// Sync the completed file to disk, and move it into place
+pub fn persist(out: Output, path: &Path) -> Result<()> {
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    file.as_file().sync_all()?;
+    file.persist(path)
+        .map_err(|e| e.error)
+        .with_context(|| anyhow!("renaming into place at {path:?}"))?;
+    #[cfg(unix)]
+    fs::File::open(parent(path))?.sync_all()?;
+    Ok(())
+}
+
+/// Atomically replace `path` with `contents`
+pub fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut out = create(path)?;
+    out.write_all(contents)?;
+    persist(out, path)
+}
+
+fn parent(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
@@ -0,0 +1,355 @@
+use std::fs;
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use archiv::{Codec, Compress, CompressOptions};
+use clap::{ArgGroup, Args, ValueEnum};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use walkdir::WalkDir;
+
+use crate::output;
+
+/// How, and how hard, to compress an output archiv
+#[derive(Args)]
+#[command(group(ArgGroup::new("kind").args(["items", "stream"])))]
+pub struct CompressArgs {
+    /// Compress each item individually, so items can be read without reading the whole archiv
+    #[arg(long)]
+    pub items: bool,
+
+    /// Compress the whole archiv as a single stream (the default)
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Compress with this codec, instead of zstd
+    #[arg(long)]
+    pub codec: Option<CodecArg>,
+
+    /// zstd compression level, or xz preset
+    #[arg(short, long)]
+    pub level: Option<i32>,
+
+    /// Compress using a dictionary, as produced by `train`
+    #[arg(long)]
+    pub dict: Option<PathBuf>,
+
+    /// Write a trailer, with the item count, sizes and a checksum, so `stats` doesn't have to read
+    /// every item, and truncation can be detected
+    #[arg(long)]
+    pub trailer: bool,
+
+    /// Store items which don't get any smaller when compressed, with --items; older versions of
+    /// archiv can't read the result
+    #[arg(long)]
+    pub stored_items: bool,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+pub enum CodecArg {
+    Zstd,
+    #[cfg(feature = "lz4")]
+    Lz4,
+    #[cfg(feature = "xz")]
+    Xz,
+}
+
+impl From<CodecArg> for Codec {
+    fn from(val: CodecArg) -> Codec {
+        match val {
+            CodecArg::Zstd => Codec::Zstd,
+            #[cfg(feature = "lz4")]
+            CodecArg::Lz4 => Codec::Lz4,
+            #[cfg(feature = "xz")]
+            CodecArg::Xz => Codec::Xz,
+        }
+    }
+}
+
+/// How items are separated in the input
+#[derive(Args)]
+#[command(group(ArgGroup::new("from").args(["from_lines", "from_nul", "from_lenprefix"])))]
+pub struct InputArgs {
+    /// Each line (without its trailing newline) is an item
+    #[arg(long)]
+    pub from_lines: bool,
+
+    /// Items are separated (or terminated) by NUL bytes
+    #[arg(long)]
+    pub from_nul: bool,
+
+    /// Items are each preceded by their length, as a 64-bit little-endian integer
+    #[arg(long)]
+    pub from_lenprefix: bool,
+}
+
+/// Which files to pack, when given directories
+#[derive(Args)]
+pub struct WalkArgs {
+    /// Pack the files in directories, recursively, in sorted order
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Only pack files whose path (relative to the directory) matches one of these globs
+    #[arg(long, value_name = "GLOB", requires = "recursive")]
+    pub include: Vec<String>,
+
+    /// Skip files and directories whose path (relative to the directory) matches one of these globs
+    #[arg(long, value_name = "GLOB", requires = "recursive")]
+    pub exclude: Vec<String>,
+
+    /// Follow symlinks while walking directories, instead of skipping them
+    #[arg(short = 'L', long, requires = "recursive")]
+    pub follow_symlinks: bool,
+
+    /// Write the index, offset and path of each item to this file, tab separated;
+    /// paths found by walking a directory are relative to it
+    #[arg(long, value_name = "PATH")]
+    pub manifest: Option<PathBuf>,
+}
+
+/// A file to read, and the name to record for it in the manifest
+struct Source {
+    path: PathBuf,
+    name: PathBuf,
+}
+
+impl WalkArgs {
+    fn sources(&self, files: &[PathBuf]) -> Result<Vec<Source>> {
+        let include = glob_set(&self.include)?;
+        let exclude = glob_set(&self.exclude)?;
+        let mut sources = Vec::with_capacity(files.len());
+        for file in files {
+            let is_dir = file != Path::new("-")
+                && fs::metadata(file)
+                    .with_context(|| anyhow!("{file:?}"))?
+                    .is_dir();
+            if !is_dir {
+                sources.push(Source {
+                    path: file.to_path_buf(),
+                    name: file.to_path_buf(),
+                });
+                continue;
+            }
+            if !self.recursive {
+                bail!("{file:?} is a directory; use --recursive to pack its contents");
+            }
+            let walk = WalkDir::new(file)
+                .follow_links(self.follow_symlinks)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|entry| {
+                    entry.depth() == 0 || !exclude.is_match(relative(file, entry.path()))
+                });
+            for entry in walk {
+                let entry = entry.with_context(|| anyhow!("walking {file:?}"))?;
+                // symlinks are only returned here when they aren't being followed
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let name = relative(file, entry.path());
+                if !self.include.is_empty() && !include.is_match(name) {
+                    continue;
+                }
+                sources.push(Source {
+                    name: name.to_path_buf(),
+                    path: entry.into_path(),
+                });
+            }
+        }
+        Ok(sources)
+    }
+}
+
+fn relative<'p>(root: &Path, path: &'p Path) -> &'p Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+fn glob_set(globs: &[String]) -> Result<GlobSet> {
+    let mut set = GlobSetBuilder::new();
+    for glob in globs {
+        set.add(Glob::new(glob).with_context(|| anyhow!("parsing glob {glob:?}"))?);
+    }
+    Ok(set.build()?)
+}
+
+#[derive(Copy, Clone)]
+enum Framing {
+    Whole,
+    Delimited(u8),
+    LenPrefix,
+}
+
+impl InputArgs {
+    fn framing(&self) -> Option<Framing> {
+        if self.from_lines {
+            Some(Framing::Delimited(b'\n'))
+        } else if self.from_nul {
+            Some(Framing::Delimited(0))
+        } else if self.from_lenprefix {
+            Some(Framing::LenPrefix)
+        } else {
+            None
+        }
+    }
+}
+
+impl CompressArgs {
+    pub fn load_dict(&self) -> Result<Option<Vec<u8>>> {
+        self.dict
+            .as_ref()
+            .map(|path| fs::read(path).with_context(|| anyhow!("reading dictionary {path:?}")))
+            .transpose()
+    }
+
+    pub fn options<'d>(&self, dict: Option<&'d [u8]>) -> CompressOptions<'d> {
+        let mut opts = CompressOptions::default();
+        if let Some(codec) = self.codec {
+            opts = opts.with_codec(codec.into());
+        }
+        if let Some(level) = self.level {
+            opts = opts.with_level(level);
+        }
+        if let Some(dict) = dict {
+            opts = opts.with_dict_bytes(dict);
+        }
+        opts.with_trailer(self.trailer)
+            .with_stored_items(self.stored_items)
+    }
+}
+
+/// Pack files, or records from files (or stdin), into an archiv
+pub fn pack(
+    files: &[PathBuf],
+    walk: &WalkArgs,
+    input: &InputArgs,
+    compress: &CompressArgs,
+    output: Option<&Path>,
+) -> Result<()> {
+    let framing = input.framing();
+    let files = match (framing, files.is_empty()) {
+        (None, true) => bail!("files are required, unless reading records with --from-*"),
+        (Some(_), true) => vec![PathBuf::from("-")],
+        (_, false) => files.to_vec(),
+    };
+    let framing = framing.unwrap_or(Framing::Whole);
+    let sources = walk.sources(&files)?;
+    let mut manifest = walk.manifest.as_deref().map(output::create).transpose()?;
+
+    let dict = compress.load_dict()?;
+    let opts = compress.options(dict.as_deref());
+    let pack = Pack {
+        items: compress.items,
+        opts: &opts,
+        sources: &sources,
+        framing,
+    };
+    let listing = manifest.as_mut().map(|m| m as &mut dyn Write);
+    match output {
+        Some(path) => output::persist(pack.write_to(output::create(path)?, listing)?, path)?,
+        None => {
+            let _ = pack.write_to(io::stdout().lock(), listing)?;
+        }
+    }
+    if let (Some(manifest), Some(path)) = (manifest, &walk.manifest) {
+        output::persist(manifest, path)?;
+    }
+    Ok(())
+}
+
+struct Pack<'a> {
+    items: bool,
+    opts: &'a CompressOptions<'a>,
+    sources: &'a [Source],
+    framing: Framing,
+}
+
+impl Pack<'_> {
+    fn write_to<W: Write>(&self, out: W, manifest: Option<&mut dyn Write>) -> Result<W> {
+        if self.items {
+            write_all(
+                self.opts.item_compress(out)?,
+                self.sources,
+                self.framing,
+                manifest,
+            )
+        } else {
+            write_all(
+                self.opts.stream_compress(out)?,
+                self.sources,
+                self.framing,
+                manifest,
+            )
+        }
+    }
+}
+
+fn write_all<W: Write>(
+    mut archiv: impl Compress<W>,
+    sources: &[Source],
+    framing: Framing,
+    mut manifest: Option<&mut dyn Write>,
+) -> Result<W> {
+    let mut buf = Vec::with_capacity(4096);
+    let mut index = 0u64;
+    for Source { path: file, name } in sources {
+        let input: Box<dyn BufRead> = if file == Path::new("-") {
+            Box::new(io::stdin().lock())
+        } else {
+            let f = fs::File::open(file).with_context(|| anyhow!("{file:?}"))?;
+            Box::new(io::BufReader::new(f))
+        };
+        each_record(input, framing, &mut buf, |item| {
+            let offset = archiv.write_item_vectored(&[item])?;
+            if let Some(manifest) = manifest.as_mut() {
+                writeln!(manifest, "{index}\t{offset}\t{}", name.display())?;
+            }
+            index += 1;
+            Ok(())
+        })
+        .with_context(|| anyhow!("{file:?}"))?;
+    }
+    Ok(archiv.finish()?)
+}
+
+fn each_record(
+    mut input: impl BufRead,
+    framing: Framing,
+    buf: &mut Vec<u8>,
+    mut f: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<()> {
+    loop {
+        buf.clear();
+        match framing {
+            Framing::Whole => {
+                input.read_to_end(buf)?;
+                return f(buf);
+            }
+            Framing::Delimited(delim) => {
+                if input.read_until(delim, buf)? == 0 {
+                    return Ok(());
+                }
+                if buf.last() == Some(&delim) {
+                    buf.pop();
+                }
+            }
+            Framing::LenPrefix => {
+                if input.fill_buf()?.is_empty() {
+                    return Ok(());
+                }
+                let mut len = [0u8; 8];
+                input.read_exact(&mut len)?;
+                let len = u64::from_le_bytes(len);
+                (&mut input).take(len).read_to_end(buf)?;
+                if u64::try_from(buf.len())? != len {
+                    bail!(
+                        "truncated record, expected {len} bytes, found {}",
+                        buf.len()
+                    );
+                }
+            }
+        }
+        f(buf)?;
+    }
+}
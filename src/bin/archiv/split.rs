@@ -0,0 +1,208 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use archiv::{
+    Compress, CompressItem, CompressOptions, CompressPlain, CompressStream, DecoderDictionary,
+    ExpandOptions, Layout, RawExpand,
+};
+use zstd::zstd_safe::get_dict_id_from_dict;
+
+use crate::output;
+use crate::pack::CompressArgs;
+
+/// Where to start a new part
+#[derive(Copy, Clone)]
+pub struct Limits {
+    pub items: Option<u64>,
+    pub bytes: Option<u64>,
+}
+
+/// An archiv being written, of any kind
+enum Writer<'d> {
+    Items(CompressItem<'d, output::Output>),
+    Stream(CompressStream<'d, output::Output>),
+    Plain(CompressPlain<output::Output>),
+}
+
+impl<'d> Writer<'d> {
+    fn create(path: &Path, layout: Layout, opts: &CompressOptions<'d>) -> Result<Self> {
+        let out = output::create(path)?;
+        Ok(match layout {
+            Layout::ItemCompressed => Writer::Items(opts.item_compress(out)?),
+            Layout::StreamCompressed => Writer::Stream(opts.stream_compress(out)?),
+            Layout::Plain => Writer::Plain(opts.plain(out)?),
+        })
+    }
+
+    /// write an item read by `next_raw`, reusing an item-compressed frame if `passthrough`
+    fn write_raw(
+        &mut self,
+        archiv: &RawExpand<'_, impl io::BufRead>,
+        raw: &[u8],
+        passthrough: bool,
+        buf: &mut Vec<u8>,
+    ) -> Result<()> {
+        match self {
+            Writer::Items(w)
+                if passthrough
+                    && archiv.layout() == Layout::ItemCompressed
+                    && archiv.codec() == Some(w.codec())
+                    && (w.stored_items() || !archiv.stored_items()) =>
+            {
+                w.write_frame(raw)?;
+            }
+            Writer::Items(w) => {
+                archiv.expand_raw(raw, buf)?;
+                w.write_item(buf)?;
+            }
+            Writer::Stream(w) => {
+                archiv.expand_raw(raw, buf)?;
+                w.write_item(buf)?;
+            }
+            Writer::Plain(w) => {
+                archiv.expand_raw(raw, buf)?;
+                w.write_item(buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self, path: &Path) -> Result<()> {
+        let out = match self {
+            Writer::Items(w) => w.finish()?,
+            Writer::Stream(w) => w.finish()?,
+            Writer::Plain(w) => w.finish()?,
+        };
+        output::persist(out, path)
+    }
+}
+
+/// Dictionaries to read and write with, loaded from `--dict`
+struct Dicts {
+    bytes: Option<Vec<u8>>,
+    decoder: Option<DecoderDictionary<'static>>,
+}
+
+impl Dicts {
+    fn load(compress: &CompressArgs) -> Result<Dicts> {
+        let bytes = compress.load_dict()?;
+        let decoder = bytes.as_deref().map(DecoderDictionary::copy);
+        Ok(Dicts { bytes, decoder })
+    }
+
+    fn expand(&self) -> ExpandOptions<'_> {
+        match &self.decoder {
+            Some(dict) => ExpandOptions::default().with_dict(dict),
+            None => ExpandOptions::default(),
+        }
+    }
+
+    fn id(&self) -> Option<u32> {
+        self.bytes
+            .as_deref()
+            .and_then(get_dict_id_from_dict)
+            .map(u32::from)
+    }
+}
+
+fn open<'d>(
+    opts: &ExpandOptions<'d>,
+    file: &Path,
+) -> Result<RawExpand<'d, Box<dyn io::BufRead + 'd>>> {
+    let f = fs::File::open(file).with_context(|| anyhow!("{file:?}"))?;
+    opts.raw(io::BufReader::new(f))
+        .with_context(|| anyhow!("{file:?}"))
+}
+
+/// Split an archiv into numbered parts of the same kind, named `PREFIX.0000.archiv` etc.
+pub fn split(
+    file: &Path,
+    limits: Limits,
+    prefix: Option<&Path>,
+    compress: &CompressArgs,
+) -> Result<()> {
+    if limits.items == Some(0) || limits.bytes == Some(0) {
+        bail!("limits must be positive");
+    }
+    let prefix = prefix.map_or_else(|| file.with_extension(""), Path::to_path_buf);
+    let dicts = Dicts::load(compress)?;
+    let expand = dicts.expand();
+    let mut archiv = open(&expand, file)?;
+    let layout = archiv.layout();
+    let mut opts = compress
+        .options(dicts.bytes.as_deref())
+        .with_stored_items(archiv.stored_items());
+    if let Some(codec) = archiv.codec() {
+        opts = opts.with_codec(codec);
+    }
+
+    let mut raw = Vec::with_capacity(4096);
+    let mut buf = Vec::with_capacity(4096);
+    let mut part = 0;
+    let mut path = part_path(&prefix, part);
+    let mut w = Writer::create(&path, layout, &opts)?;
+    let (mut part_items, mut part_bytes) = (0u64, 0u64);
+    while archiv.next_raw(&mut raw)?.is_some() {
+        let stored = 8 + raw.len() as u64;
+        if part_items > 0
+            && (limits.items.is_some_and(|max| part_items >= max)
+                || limits.bytes.is_some_and(|max| part_bytes + stored > max))
+        {
+            w.finish(&path)?;
+            part += 1;
+            path = part_path(&prefix, part);
+            w = Writer::create(&path, layout, &opts)?;
+            (part_items, part_bytes) = (0, 0);
+        }
+        // parts keep the input's frames, whatever dictionary they were compressed with
+        w.write_raw(&archiv, &raw, true, &mut buf)?;
+        part_items += 1;
+        part_bytes += stored;
+    }
+    archiv.check_eof()?;
+    w.finish(&path)
+}
+
+fn part_path(prefix: &Path, part: u64) -> PathBuf {
+    let mut name = prefix.as_os_str().to_os_string();
+    name.push(format!(".{part:04}.archiv"));
+    PathBuf::from(name)
+}
+
+/// Concatenate the items of several archivs into one
+pub fn merge(files: &[PathBuf], out: &Path, compress: &CompressArgs) -> Result<()> {
+    let dicts = Dicts::load(compress)?;
+    let opts = compress.options(dicts.bytes.as_deref());
+    let dict_id = dicts.id();
+    let expand = dicts.expand();
+
+    let mut writer = None;
+    let mut raw = Vec::with_capacity(4096);
+    let mut buf = Vec::with_capacity(4096);
+    for file in files {
+        let mut archiv = open(&expand, file)?;
+        let w = match &mut writer {
+            Some(w) => w,
+            None => {
+                let layout = match (compress.items, compress.stream) {
+                    (true, _) => Layout::ItemCompressed,
+                    (_, true) => Layout::StreamCompressed,
+                    // the same kind as the first input
+                    _ => archiv.layout(),
+                };
+                writer.insert(Writer::create(out, layout, &opts)?)
+            }
+        };
+        while let Some(item) = archiv.next_raw(&mut raw)? {
+            w.write_raw(&archiv, &raw, item.dict_id == dict_id, &mut buf)
+                .with_context(|| anyhow!("{file:?}: item {}", item.index))?;
+        }
+        archiv.check_eof().with_context(|| anyhow!("{file:?}"))?;
+    }
+    match writer {
+        Some(w) => w.finish(out),
+        None => bail!("no archivs to merge"),
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use archiv::{Codec, Error, ExpandOptions, Layout, Trailer};
+use serde_json::{json, Value};
+
+// the rest are counted, but not printed, except with --json
+const MAX_PRINTED_ERRORS: usize = 10;
+
+#[derive(Default)]
+struct Stats {
+    path: PathBuf,
+    file_bytes: u64,
+    layout: Option<Layout>,
+    codec: Option<Codec>,
+    metadata: Vec<(String, String)>,
+    header: Option<String>,
+    footer: Option<String>,
+    // the items couldn't be followed to the footer
+    stopped: bool,
+    item_errors: Vec<String>,
+    trailer: Option<Trailer>,
+    items: u64,
+    stored_bytes: u64,
+    uncompressed_bytes: u64,
+    unknown_sizes: u64,
+    sizes: Vec<u64>,
+    dict_ids: BTreeSet<u32>,
+}
+
+pub fn stats(files: &[PathBuf], as_json: bool, decompress: bool) -> Result<()> {
+    let mut all = Vec::with_capacity(files.len());
+    for file in files {
+        let stats = collect(file, decompress).with_context(|| anyhow!("{file:?}"))?;
+        if as_json {
+            all.push(stats.to_json());
+        } else {
+            stats.print();
+        }
+    }
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&all)?);
+    }
+    Ok(())
+}
+
+fn collect(path: &Path, decompress: bool) -> Result<Stats> {
+    let mut file = fs::File::open(path)?;
+    let mut stats = Stats {
+        path: path.to_path_buf(),
+        file_bytes: file.metadata()?.len(),
+        ..Stats::default()
+    };
+
+    // other problems are found by reading the archive
+    stats.trailer = match ExpandOptions::default().trailer(&mut file) {
+        Ok(trailer) => trailer,
+        Err(e @ Error::TrailerMissing { .. }) => {
+            stats.footer = Some(format!("{:#}", anyhow!(e)));
+            None
+        }
+        Err(_) => None,
+    };
+    file.seek(SeekFrom::Start(0))?;
+
+    let mut archiv = match ExpandOptions::default().raw(io::BufReader::new(file)) {
+        Ok(archiv) => archiv,
+        Err(e) => {
+            stats.header = Some(format!("{:#}", anyhow!(e)));
+            return Ok(stats);
+        }
+    };
+    stats.layout = Some(archiv.layout());
+    stats.codec = archiv.codec();
+    stats.metadata = archiv.archive_metadata().to_vec();
+    stats.dict_ids.extend(archiv.dict_id());
+
+    if let Some(trailer) = stats.trailer.filter(|_| !decompress) {
+        stats.items = trailer.items;
+        stats.stored_bytes = trailer.stored_bytes;
+        match trailer.uncompressed_bytes {
+            Some(bytes) => stats.uncompressed_bytes = bytes,
+            None => stats.unknown_sizes = trailer.items,
+        }
+        return Ok(stats);
+    }
+
+    let mut raw = Vec::with_capacity(4096);
+    let mut out = Vec::with_capacity(4096);
+    loop {
+        let item = match archiv.next_raw(&mut raw) {
+            Ok(Some(item)) => item,
+            Ok(None) => break,
+            Err(e) => {
+                // the item's length is unknown, so nothing after it can be found
+                stats
+                    .item_errors
+                    .push(item_error(archiv.index(), archiv.offset(), e));
+                stats.stopped = true;
+                break;
+            }
+        };
+        stats.items += 1;
+        stats.stored_bytes += item.stored_len;
+        stats.dict_ids.extend(item.dict_id);
+
+        let size = match item.content_len {
+            Some(size) if !decompress => Some(size),
+            _ if decompress => match archiv.expand_raw(&raw, &mut out) {
+                Ok(()) => Some(u64::try_from(out.len())?),
+                Err(e) => {
+                    stats
+                        .item_errors
+                        .push(item_error(item.index, item.offset, e));
+                    None
+                }
+            },
+            _ => None,
+        };
+        match size {
+            Some(size) => {
+                stats.uncompressed_bytes += size;
+                stats.sizes.push(size);
+            }
+            None => stats.unknown_sizes += 1,
+        }
+    }
+    if !stats.stopped && stats.footer.is_none() {
+        stats.footer = archiv
+            .check_eof()
+            .err()
+            .map(|e| format!("{:#}", anyhow!(e)));
+        stats.trailer = stats.trailer.or(archiv.trailer());
+    }
+
+    stats.sizes.sort_unstable();
+    Ok(stats)
+}
+
+fn item_error(index: u64, offset: u64, source: Error) -> String {
+    let e = Error::At {
+        index: Some(index),
+        offset,
+        source: Box::new(source),
+    };
+    format!("{:#}", anyhow!(e))
+}
+
+fn layout_name(layout: Layout) -> &'static str {
+    match layout {
+        Layout::Plain => "plain",
+        Layout::StreamCompressed => "stream-compressed",
+        Layout::ItemCompressed => "item-compressed",
+    }
+}
+
+impl Stats {
+    fn percentile(&self, p: usize) -> Option<u64> {
+        let last = self.sizes.len().checked_sub(1)?;
+        Some(self.sizes[last * p / 100])
+    }
+
+    fn ratio(&self) -> Option<f64> {
+        if self.file_bytes == 0 || self.items == self.unknown_sizes {
+            return None;
+        }
+        Some(self.uncompressed_bytes as f64 / self.file_bytes as f64)
+    }
+
+    /// (inclusive upper bound, count), with power-of-two bounds
+    fn histogram(&self) -> Vec<(u64, u64)> {
+        let mut buckets: Vec<(u64, u64)> = Vec::new();
+        for &size in &self.sizes {
+            let bound = size.checked_next_power_of_two().unwrap_or(u64::MAX);
+            match buckets.last_mut() {
+                Some((last, count)) if *last == bound => *count += 1,
+                _ => buckets.push((bound, 1)),
+            }
+        }
+        buckets
+    }
+
+    fn print(&self) {
+        println!("{}:", self.path.display());
+        let Some(layout) = self.layout else {
+            println!(
+                "  header: invalid: {}",
+                self.header.as_deref().unwrap_or("?")
+            );
+            return;
+        };
+        println!("  kind: {}", layout_name(layout));
+        if let Some(codec) = self.codec {
+            println!("  codec: {}", codec.name());
+        }
+        println!("  header: ok");
+        if !self.metadata.is_empty() {
+            println!("  metadata:");
+            for (key, value) in &self.metadata {
+                println!("    {key}: {value}");
+            }
+        }
+        match &self.footer {
+            None if self.stopped => println!("  footer: not reached"),
+            None => println!("  footer: ok"),
+            Some(e) => println!("  footer: invalid: {e}"),
+        }
+        if !self.item_errors.is_empty() {
+            println!("  item errors: {}", self.item_errors.len());
+            for e in self.item_errors.iter().take(MAX_PRINTED_ERRORS) {
+                println!("    {e}");
+            }
+            if self.item_errors.len() > MAX_PRINTED_ERRORS {
+                println!("    ...");
+            }
+        }
+        if let Some(trailer) = &self.trailer {
+            println!("  trailer: checksum {:016x}", trailer.checksum);
+        }
+        println!("  items: {}", self.items);
+        print!(
+            "  bytes: {} on disk, {} stored, {} uncompressed",
+            self.file_bytes, self.stored_bytes, self.uncompressed_bytes
+        );
+        match self.ratio() {
+            Some(ratio) => println!(" ({ratio:.2}x)"),
+            None => println!(),
+        }
+        if self.unknown_sizes != 0 {
+            println!(
+                "  {} items don't declare their size (use --decompress)",
+                self.unknown_sizes
+            );
+        }
+        match self.dict_ids.len() {
+            0 => println!("  dictionary: none"),
+            _ => println!(
+                "  dictionary: {}",
+                self.dict_ids
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+
+        let (Some(min), Some(max)) = (self.sizes.first(), self.sizes.last()) else {
+            if self.trailer.is_some() && self.items != 0 {
+                println!(
+                    "  item sizes: not read, as the trailer has the totals (use --decompress)"
+                );
+            }
+            return;
+        };
+        println!(
+            "  item sizes: min {min}, median {}, p90 {}, p99 {}, max {max}, mean {}",
+            self.percentile(50).unwrap_or_default(),
+            self.percentile(90).unwrap_or_default(),
+            self.percentile(99).unwrap_or_default(),
+            self.uncompressed_bytes / self.sizes.len() as u64,
+        );
+        println!("  histogram:");
+        for (bound, count) in self.histogram() {
+            println!("    <= {bound:>12}: {count}");
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "path": self.path,
+            "file_bytes": self.file_bytes,
+            "kind": self.layout.map(layout_name),
+            "codec": self.codec.map(Codec::name),
+            "metadata": self.metadata
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": value }))
+                .collect::<Vec<_>>(),
+            "header": { "valid": self.layout.is_some(), "error": self.header },
+            "footer": {
+                "valid": self.layout.is_some() && !self.stopped && self.footer.is_none(),
+                "error": self.footer,
+            },
+            "item_errors": self.item_errors,
+            "trailer": self.trailer.map(|trailer| json!({ "checksum": format!("{:016x}", trailer.checksum) })),
+            "items": self.items,
+            "stored_bytes": self.stored_bytes,
+            "uncompressed_bytes": self.uncompressed_bytes,
+            "unknown_sizes": self.unknown_sizes,
+            "ratio": self.ratio(),
+            "dictionary_ids": self.dict_ids,
+            "item_sizes": {
+                "min": self.sizes.first(),
+                "median": self.percentile(50),
+                "p90": self.percentile(90),
+                "p99": self.percentile(99),
+                "max": self.sizes.last(),
+            },
+            "histogram": self.histogram()
+                .into_iter()
+                .map(|(le, count)| json!({ "le": le, "count": count }))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
@@ -0,0 +1,29 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use archiv::ExpandOptions;
+
+/// Returns `true` if every archive is valid
+pub fn verify(files: &[PathBuf]) -> Result<bool> {
+    let mut all_ok = true;
+    for file in files {
+        let f = fs::File::open(file).with_context(|| anyhow!("{file:?}"))?;
+        let report = ExpandOptions::default().verify(io::BufReader::new(f));
+        match report.failure {
+            None => println!("{}: ok, {} items", file.display(), report.items),
+            Some(failure) => {
+                all_ok = false;
+                println!(
+                    "{}: FAILED at item {} (offset {}): {:#}",
+                    file.display(),
+                    failure.index,
+                    failure.offset,
+                    anyhow!(failure.error)
+                );
+            }
+        }
+    }
+    Ok(all_ok)
+}
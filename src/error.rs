@@ -6,15 +6,72 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Library specific errors we can encounter, typically around underlying or format errors
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    #[error("this isn't the right type of file for us")]
-    MagicMissing,
-    #[error("this looks like the right kind of file, but uses features we can't handle")]
-    MagicUnrecognised,
+    #[error(
+        "this isn't the right type of file for us: found {} at offset {offset}{}",
+        hex(.found),
+        looks_like(.guess)
+    )]
+    MagicMissing {
+        offset: u64,
+        /// the first few bytes, where the magic should have been
+        found: Vec<u8>,
+        /// what the file might be instead, e.g. "gzip" or "a tar file"
+        guess: Option<String>,
+    },
+    #[error(
+        "this looks like the right kind of file, but uses features we can't handle: \
+         {what} {value} at offset {offset}"
+    )]
+    MagicUnrecognised {
+        offset: u64,
+        /// the field with an unexpected value, e.g. "kind"
+        what: &'static str,
+        value: u8,
+    },
+    #[error("this file needs the '{0}' codec, which wasn't enabled at build time")]
+    CodecUnavailable(&'static str),
 
+    #[error("the archive's extended header is malformed")]
+    InvalidHeader,
     #[error("an item exceeded the specified limits")]
     InvalidItem,
+    #[error("offset {offset} isn't the start of an item")]
+    NotItemBoundary { offset: u64 },
+    #[error("the archive is inside more than {max} layers of stream compression")]
+    TooManyWrappers { max: usize },
+    #[error("stream-compressed archives can't be read from an offset without a seek table")]
+    NotSeekable,
+    #[error("there is unexpected data after the end of the archive")]
+    TrailingData,
+    #[error("an item declared a size of {declared} bytes, but contained {actual}")]
+    ContentSizeMismatch { declared: u64, actual: u64 },
+    #[error(
+        "the archive's trailer is missing, so it may be truncated; expected at offset {offset}"
+    )]
+    TrailerMissing { offset: u64 },
+    #[error("the archive's trailer records {recorded} for its {what}, but found {actual}")]
+    TrailerMismatch {
+        /// the field which didn't match, e.g. "item count" or "checksum"
+        what: &'static str,
+        recorded: u64,
+        actual: u64,
+    },
     #[error("invalid use of the API")]
     ApiMisuse,
+    #[error("{}at offset {offset}", item_index(.index))]
+    At {
+        /// the index of the item, if known
+        index: Option<u64>,
+        /// where the item started in the file, or how far into the file a stream-compressed
+        /// archive had been read, which is after the item, as the decompressor reads ahead
+        offset: u64,
+        source: Box<Error>,
+    },
+
+    #[error("couldn't encode or decode a typed item")]
+    Encoding {
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
 
     #[error("overflow during a 64-bit math operation (unlikely)")]
     LengthOverflow,
@@ -31,3 +88,50 @@ pub enum Error {
     #[error("unexpected internal error: {0}")]
     Internal(&'static str),
 }
+
+impl Error {
+    /// note that a missing magic was found inside a layer of stream compression
+    pub(crate) fn inside(self, wrapper: &str) -> Error {
+        match self {
+            Error::MagicMissing {
+                offset,
+                found,
+                guess,
+            } => Error::MagicMissing {
+                offset,
+                found,
+                guess: Some(match guess {
+                    Some(guess) => format!("{guess}, inside {wrapper}"),
+                    None => format!("{wrapper}, but not an archiv"),
+                }),
+            },
+            other => other,
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "nothing".to_string();
+    }
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("[{hex}]")
+}
+
+fn item_index(index: &Option<u64>) -> String {
+    match index {
+        Some(index) => format!("item {index}, "),
+        None => String::new(),
+    }
+}
+
+fn looks_like(guess: &Option<String>) -> String {
+    match guess {
+        Some(guess) => format!(", which looks like {guess}"),
+        None => String::new(),
+    }
+}
@@ -1,42 +1,328 @@
+use std::io;
+use std::io::Read;
+
 use crate::error::{Error, Result};
+use crate::zbuild::Codec;
 
 //                          (all other values reserved)
-//                                Kinds enum   ----v
+//                              version  ----v   v---- Kinds enum
 pub const HEADER_TEMPLATE: [u8; 8] = *b"\x29\xb6arc\0\0\0";
 const FOOTER_TEMPLATE: [u8; 8] = u64::to_le_bytes(0xffff_ffff_ffff_fff0);
 pub const GLOBAL_MARKER_LEN: u64 = 8;
 
+// version 0 is just the template; version 1 adds a flags word (u32 le), then an extension area,
+// of a u32 le length, then repeatedly: a tag (u16 le), a length (u32 le), and the value
+const VERSION_EXTENDED: u8 = 1;
+// flags are features of the archive: the low 16 bits are ones a reader must understand, and the
+// high 16 bits are ones it can ignore, and still read the archive
+const REQUIRED_FLAGS: u32 = 0xffff;
+// items may be stored uncompressed, as zstd skippable frames, which older readers would read as empty
+const FLAG_STORED_ITEMS: u32 = 1;
+// a trailer, with a summary of the archive, follows the footer, where older readers stop reading
+const FLAG_TRAILER: u32 = 1 << 16;
+const KNOWN_FLAGS: u32 = FLAG_STORED_ITEMS | FLAG_TRAILER;
+const MAX_EXTENSIONS_LEN: u32 = 16 * 1024 * 1024;
+
+// extension tags; each metadata entry is a u32 le key length, the key, then the value
+const TAG_METADATA: u16 = 1;
+
 // 2^63.9 bytes, over 17 million terabytes.
 // (obviously this is ridiculous)
 pub const MAX_ITEM_SIZE: u64 = 0xf000_0000_0000_0000;
 
 pub const ZSTD_MAGIC: [u8; 4] = *b"\x28\xb5\x2f\xfd";
+pub const LZ4_MAGIC: [u8; 4] = *b"\x04\x22\x4d\x18";
+pub const XZ_MAGIC: [u8; 6] = *b"\xfd7zXZ\0";
+pub const GZIP_MAGIC: [u8; 2] = *b"\x1f\x8b";
+pub const BZIP2_MAGIC: [u8; 3] = *b"BZh";
+
+// a stored (uncompressed) item is a zstd skippable frame holding the item, whatever the codec
+pub const STORED_MAGIC: [u8; 4] = *b"\x5e\x2a\x4d\x18";
+pub const STORED_HEADER_LEN: u64 = 8;
 
 #[repr(u8)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Kinds {
     Plain = 0,
     ItemCompressed = 1,
+    ItemLz4 = 2,
+    ItemXz = 3,
+}
+
+impl Kinds {
+    pub fn items(codec: Codec) -> Kinds {
+        match codec {
+            Codec::Zstd => Kinds::ItemCompressed,
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => Kinds::ItemLz4,
+            #[cfg(feature = "xz")]
+            Codec::Xz => Kinds::ItemXz,
+        }
+    }
+
+    /// the codec each item is compressed with, if any
+    pub fn item_codec(self) -> Result<Option<Codec>> {
+        Ok(match self {
+            Kinds::Plain => None,
+            Kinds::ItemCompressed => Some(Codec::Zstd),
+            #[cfg(feature = "lz4")]
+            Kinds::ItemLz4 => Some(Codec::Lz4),
+            #[cfg(not(feature = "lz4"))]
+            Kinds::ItemLz4 => return Err(Error::CodecUnavailable("lz4")),
+            #[cfg(feature = "xz")]
+            Kinds::ItemXz => Some(Codec::Xz),
+            #[cfg(not(feature = "xz"))]
+            Kinds::ItemXz => return Err(Error::CodecUnavailable("xz")),
+        })
+    }
+}
+
+/// An archive's header, including any extended fields
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub kind: Kinds,
+    /// features of the archive, some of which a reader must understand to read it
+    pub flags: u32,
+    /// (tag, value) fields, which readers skip if they don't understand them
+    pub extensions: Vec<(u16, Vec<u8>)>,
+}
+
+impl Header {
+    pub fn new(kind: Kinds) -> Header {
+        Header {
+            kind,
+            flags: 0,
+            extensions: Vec::new(),
+        }
+    }
+
+    pub fn with_trailer(mut self, trailer: bool) -> Header {
+        if trailer {
+            self.flags |= FLAG_TRAILER;
+        }
+        self
+    }
+
+    pub fn has_trailer(&self) -> bool {
+        self.flags & FLAG_TRAILER != 0
+    }
+
+    pub fn with_stored_items(mut self, stored: bool) -> Header {
+        if stored {
+            self.flags |= FLAG_STORED_ITEMS;
+        }
+        self
+    }
+
+    pub fn has_stored_items(&self) -> bool {
+        self.flags & FLAG_STORED_ITEMS != 0
+    }
+
+    /// add a metadata entry per (key, value) pair
+    pub fn with_metadata(mut self, metadata: &[(String, String)]) -> Result<Header> {
+        for (key, value) in metadata {
+            let key_len = u32::try_from(key.len()).map_err(|_| Error::LengthOverflow)?;
+            let mut entry = Vec::with_capacity(4 + key.len() + value.len());
+            entry.extend_from_slice(&key_len.to_le_bytes());
+            entry.extend_from_slice(key.as_bytes());
+            entry.extend_from_slice(value.as_bytes());
+            self.extensions.push((TAG_METADATA, entry));
+        }
+        Ok(self)
+    }
+
+    /// the metadata entries, in the order they were written
+    pub fn metadata(&self) -> Result<Vec<(String, String)>> {
+        self.extensions
+            .iter()
+            .filter(|(tag, _)| *tag == TAG_METADATA)
+            .map(|(_, entry)| {
+                let (key_len, rest) = entry.split_first_chunk::<4>().ok_or(Error::InvalidHeader)?;
+                let key_len = u32::from_le_bytes(*key_len) as usize;
+                if key_len > rest.len() {
+                    return Err(Error::InvalidHeader);
+                }
+                let (key, value) = rest.split_at(key_len);
+                let text =
+                    |v: &[u8]| String::from_utf8(v.to_vec()).map_err(|_| Error::InvalidHeader);
+                Ok((text(key)?, text(value)?))
+            })
+            .collect()
+    }
+
+    // headers without extended fields are written as version 0, so older readers can read them
+    fn is_extended(&self) -> bool {
+        self.flags != 0 || !self.extensions.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buf = HEADER_TEMPLATE.to_vec();
+        buf[7] = self.kind as u8;
+        if !self.is_extended() {
+            return Ok(buf);
+        }
+        buf[6] = VERSION_EXTENDED;
+        buf.extend_from_slice(&self.flags.to_le_bytes());
+        let mut area = Vec::new();
+        for (tag, value) in &self.extensions {
+            let len = u32::try_from(value.len()).map_err(|_| Error::LengthOverflow)?;
+            area.extend_from_slice(&tag.to_le_bytes());
+            area.extend_from_slice(&len.to_le_bytes());
+            area.extend_from_slice(value);
+        }
+        let area_len = u32::try_from(area.len())
+            .ok()
+            .filter(|len| *len <= MAX_EXTENSIONS_LEN)
+            .ok_or(Error::LengthOverflow)?;
+        buf.extend_from_slice(&area_len.to_le_bytes());
+        buf.extend_from_slice(&area);
+        Ok(buf)
+    }
+
+    /// the length of the header as written, which is also the offset of the first item
+    pub fn encoded_len(&self) -> u64 {
+        if !self.is_extended() {
+            return GLOBAL_MARKER_LEN;
+        }
+        let area: u64 = self
+            .extensions
+            .iter()
+            .map(|(_, value)| 2 + 4 + value.len() as u64)
+            .sum();
+        GLOBAL_MARKER_LEN + 4 + 4 + area
+    }
 }
 
-pub fn header(kind: Kinds) -> [u8; 8] {
-    let mut header = HEADER_TEMPLATE;
-    header[7] = kind as u8;
-    header
+/// read the rest of a header, of any version, from its first eight bytes, leaving `inner` at the first item
+pub fn read_header(buf: &[u8; 8], mut inner: impl Read) -> Result<Header> {
+    let mut header = Header::new(parse_header(buf)?);
+    if buf[6] != VERSION_EXTENDED {
+        return Ok(header);
+    }
+
+    let mut words = [0u8; 8];
+    inner.read_exact(&mut words)?;
+    let [f0, f1, f2, f3, l0, l1, l2, l3] = words;
+    header.flags = u32::from_le_bytes([f0, f1, f2, f3]);
+    let unknown = header.flags & REQUIRED_FLAGS & !KNOWN_FLAGS;
+    if unknown != 0 {
+        return Err(Error::MagicUnrecognised {
+            offset: 8,
+            what: "flag",
+            value: unknown.trailing_zeros() as u8,
+        });
+    }
+    let area_len = u32::from_le_bytes([l0, l1, l2, l3]);
+    if area_len > MAX_EXTENSIONS_LEN {
+        return Err(Error::InvalidHeader);
+    }
+    let mut area = Vec::new();
+    area.try_reserve_exact(area_len as usize)?;
+    (&mut inner)
+        .take(u64::from(area_len))
+        .read_to_end(&mut area)?;
+    if area.len() != area_len as usize {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    let mut rest = area.as_slice();
+    while !rest.is_empty() {
+        let (tag, after) = rest.split_first_chunk::<2>().ok_or(Error::InvalidHeader)?;
+        let (len, after) = after.split_first_chunk::<4>().ok_or(Error::InvalidHeader)?;
+        let len = u32::from_le_bytes(*len) as usize;
+        if len > after.len() {
+            return Err(Error::InvalidHeader);
+        }
+        let (value, after) = after.split_at(len);
+        header
+            .extensions
+            .push((u16::from_le_bytes(*tag), value.to_vec()));
+        rest = after;
+    }
+    Ok(header)
 }
 
+/// the kind of an archive, from its first eight bytes, of any version
 pub fn parse_header(buf: &[u8; 8]) -> Result<Kinds> {
-    if buf[..7] != HEADER_TEMPLATE[..7] {
-        return Err(Error::MagicMissing);
+    if buf[..6] != HEADER_TEMPLATE[..6] {
+        return Err(magic_missing(0, buf));
+    }
+    if buf[6] > VERSION_EXTENDED {
+        return Err(Error::MagicUnrecognised {
+            offset: 6,
+            what: "version",
+            value: buf[6],
+        });
     }
 
     Ok(match buf[7] {
         0 => Kinds::Plain,
         1 => Kinds::ItemCompressed,
-        _ => return Err(Error::MagicUnrecognised),
+        2 => Kinds::ItemLz4,
+        3 => Kinds::ItemXz,
+        kind => {
+            return Err(Error::MagicUnrecognised {
+                offset: 7,
+                what: "kind",
+                value: kind,
+            })
+        }
+    })
+}
+
+/// the error for a file which doesn't start with anything we recognise, from its first few bytes
+pub fn magic_missing(offset: u64, hints: &[u8]) -> Error {
+    Error::MagicMissing {
+        offset,
+        found: hints[..hints.len().min(16)].to_vec(),
+        guess: identify(hints).map(str::to_string),
+    }
+}
+
+/// a guess at what a file is, from its first few bytes
+fn identify(hints: &[u8]) -> Option<&'static str> {
+    let skippable = hints
+        .first_chunk::<4>()
+        .is_some_and(|m| u32::from_le_bytes(*m) & 0xffff_fff0 == 0x184d_2a50);
+    Some(match hints {
+        [] => "an empty file",
+        _ if hints.starts_with(&ZSTD_MAGIC) => "zstd",
+        _ if skippable => "a zstd skippable frame",
+        _ if hints.starts_with(&LZ4_MAGIC) => "lz4",
+        _ if hints.starts_with(&XZ_MAGIC) => "xz",
+        _ if hints.starts_with(&GZIP_MAGIC) => "gzip",
+        _ if hints.starts_with(&BZIP2_MAGIC) => "bzip2",
+        _ if hints.starts_with(b"PK\x03\x04") => "a zip file",
+        _ if hints.get(257..262) == Some(b"ustar") => "a tar file",
+        _ if hints.starts_with(&HEADER_TEMPLATE[..2]) => "an archiv with a damaged header",
+        _ => return None,
     })
 }
 
 pub fn footer() -> [u8; 8] {
     FOOTER_TEMPLATE
 }
+
+/// the header for a stored item of `len` bytes, if it's small enough to be stored
+pub fn stored_header(len: u64) -> Option<[u8; 8]> {
+    let len = u32::try_from(len).ok()?;
+    let mut header = [0u8; 8];
+    header[..4].copy_from_slice(&STORED_MAGIC);
+    header[4..].copy_from_slice(&len.to_le_bytes());
+    Some(header)
+}
+
+/// the content of a stored item, or `None` if the item is compressed
+pub fn stored_content(item: &[u8]) -> Result<Option<&[u8]>> {
+    let Some((magic, rest)) = item.split_first_chunk::<4>() else {
+        return Ok(None);
+    };
+    if *magic != STORED_MAGIC {
+        return Ok(None);
+    }
+    let (len, content) = rest.split_first_chunk::<4>().ok_or(Error::InvalidItem)?;
+    if u64::from(u32::from_le_bytes(*len)) != content.len() as u64 {
+        return Err(Error::InvalidItem);
+    }
+    Ok(Some(content))
+}
@@ -28,13 +28,26 @@
 //!
 mod error;
 mod header;
+mod raw;
 mod read;
+mod seek;
+mod trailer;
+#[cfg(feature = "serde")]
+mod typed;
+mod verify;
 mod write;
 mod zbuild;
 
 pub use error::Error;
+pub use raw::{FrameExpand, Layout, RawExpand, RawItem};
 pub use read::*;
+pub use seek::{SeekFrame, SeekTable};
+pub use trailer::Trailer;
+#[cfg(feature = "serde")]
+pub use typed::*;
+pub use verify::{VerifyFailure, VerifyReport};
 pub use write::*;
+pub use zbuild::Codec;
 
 pub use zstd::dict::{DecoderDictionary, EncoderDictionary};
 pub use zstd::stream::read::Decoder as ZDecoder;
@@ -0,0 +1,457 @@
+use std::io;
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+use zstd::bulk::Decompressor;
+use zstd::zstd_safe::{find_frame_compressed_size, get_dict_id_from_frame, get_frame_content_size};
+
+use crate::error::{Error, Result};
+use crate::header::{
+    footer, read_header, Header, GLOBAL_MARKER_LEN, MAX_ITEM_SIZE, STORED_HEADER_LEN, STORED_MAGIC,
+    ZSTD_MAGIC,
+};
+use crate::read::{stream_wrapper, Item, Opened, BULK_LIMIT};
+use crate::trailer::{Summary, Trailer, TRAILER_LEN};
+use crate::zbuild::{Codec, DecoderDict};
+use crate::ExpandOptions;
+
+/// How the items of an archive are stored
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// items are stored as-is
+    Plain,
+    /// a plain archive, inside a compressed stream
+    StreamCompressed,
+    /// each item is compressed individually, e.g. as a zstd frame
+    ItemCompressed,
+}
+
+/// Reader which yields items as they are stored, without decompressing item-compressed frames
+pub struct RawExpand<'d, R> {
+    inner: R,
+    layout: Layout,
+    codec: Option<Codec>,
+    dict_id: Option<u32>,
+    metadata: Vec<(String, String)>,
+    stored_items: bool,
+    // present if the archive has a trailer to check
+    summary: Option<Summary>,
+    trailer: Option<Trailer>,
+    max_item_size: u64,
+    zstd: DecoderDict<'d>,
+    offset: u64,
+    index: u64,
+    finished: bool,
+}
+
+/// The location and details of an item returned by [`RawExpand::next_raw`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RawItem {
+    pub index: u64,
+    /// the offset of the item's length marker, as returned by `write_item_vectored`
+    pub offset: u64,
+    /// the length of the item as stored, i.e. the compressed length, for item-compressed archives
+    pub stored_len: u64,
+    /// the length of the item after decompression, if the frame header declares it
+    pub content_len: Option<u64>,
+    /// the dictionary the item's frame was compressed with, if any
+    pub dict_id: Option<u32>,
+}
+
+impl<'d> ExpandOptions<'d> {
+    /// open an archive for reading the items as they are stored
+    pub fn raw<R: BufRead + 'd>(&self, inner: R) -> Result<RawExpand<'d, Box<dyn BufRead + 'd>>> {
+        let Opened {
+            header,
+            stream_codec,
+            wrapped,
+            dict_id,
+            inner,
+        } = self.open(Box::new(inner))?;
+        let (layout, codec) = match (header.kind.item_codec()?, wrapped) {
+            (Some(codec), _) => (Layout::ItemCompressed, Some(codec)),
+            (None, true) => (Layout::StreamCompressed, stream_codec),
+            (None, false) => (Layout::Plain, None),
+        };
+        self.raw_expand(inner, &header, layout, codec, dict_id)
+    }
+
+    /// open an archive which isn't stream-compressed, so items can be skipped without reading them
+    pub fn raw_seekable<R: BufRead + Seek + 'd>(&self, mut inner: R) -> Result<RawExpand<'d, R>> {
+        let (header, layout, codec) = read_seekable_header(&mut inner)?;
+        self.raw_expand(inner, &header, layout, codec, None)
+    }
+
+    /// a reusable decompressor for items compressed with `codec`, which can be sent to another
+    /// thread; `stored_items` is [`RawExpand::stored_items`] of the archive they're from
+    pub fn frame_expand(&self, codec: Codec, stored_items: bool) -> Result<FrameExpand<'d>> {
+        Ok(FrameExpand {
+            decompressor: self.zstd.decompressor()?,
+            zstd: self.zstd.clone(),
+            codec,
+            stored_items,
+            max_item_size: self.max_item_size,
+        })
+    }
+
+    fn raw_expand<R>(
+        &self,
+        inner: R,
+        header: &Header,
+        layout: Layout,
+        codec: Option<Codec>,
+        dict_id: Option<u32>,
+    ) -> Result<RawExpand<'d, R>> {
+        Ok(RawExpand {
+            inner,
+            layout,
+            codec,
+            dict_id,
+            metadata: header.metadata()?,
+            stored_items: header.has_stored_items(),
+            summary: if header.has_trailer() {
+                Some(Summary::new(&header.to_bytes()?))
+            } else {
+                None
+            },
+            trailer: None,
+            max_item_size: self.max_item_size,
+            zstd: self.zstd.clone(),
+            offset: header.encoded_len(),
+            index: 0,
+            finished: false,
+        })
+    }
+
+    /// read the item at an offset returned by `write_item`, from an archive which isn't stream-compressed
+    ///
+    /// The offset is checked to look like an item boundary, but this can't be certain without
+    /// reading the archive from the start.
+    pub fn item_at<R: BufRead + Seek + 'd>(
+        &self,
+        mut inner: R,
+        offset: u64,
+    ) -> Result<Box<dyn Item + 'd>> {
+        inner.seek(SeekFrom::Start(0))?;
+        let (header, layout, codec) = read_seekable_header(&mut inner)?;
+        let file_len = inner.seek(SeekFrom::End(0))?;
+        let not_boundary = Error::NotItemBoundary { offset };
+        // there must be room for at least this item's marker, and the footer
+        let room = offset
+            .checked_add(GLOBAL_MARKER_LEN * 2)
+            .is_some_and(|end| end <= file_len);
+        if offset < header.encoded_len() || !room {
+            return Err(not_boundary);
+        }
+
+        inner.seek(SeekFrom::Start(offset))?;
+        let mut marker = [0u8; 8];
+        inner.read_exact(&mut marker)?;
+        let len = u64::from_le_bytes(marker);
+        // the item, and at least the footer, must fit in the file
+        let fits = offset
+            .checked_add(GLOBAL_MARKER_LEN * 2)
+            .and_then(|v| v.checked_add(len))
+            .is_some_and(|end| end <= file_len);
+        if len > self.max_item_size || !fits {
+            return Err(not_boundary);
+        }
+
+        match layout {
+            Layout::ItemCompressed => {
+                let mut frame = Vec::new();
+                frame
+                    .try_reserve_exact(usize::try_from(len).map_err(|_| Error::LengthOverflow)?)?;
+                (&mut inner).take(len).read_to_end(&mut frame)?;
+                let stored_items = header.has_stored_items();
+                match codec.ok_or(Error::Internal("item-compressed without a codec"))? {
+                    Codec::Zstd if !(stored_items && frame.starts_with(&STORED_MAGIC)) => {
+                        if !frame.starts_with(&ZSTD_MAGIC)
+                            || find_frame_compressed_size(&frame) != Ok(frame.len())
+                        {
+                            return Err(not_boundary);
+                        }
+                        Ok(Box::new(self.zstd.decode(io::Cursor::new(frame))?))
+                    }
+                    // stored items, and other codecs, can only be checked by expanding the item
+                    codec => {
+                        let mut out = Vec::new();
+                        self.zstd
+                            .decode_item(codec, stored_items, &frame, self.max_item_size, &mut out)
+                            .map_err(|_| not_boundary)?;
+                        Ok(Box::new(io::Cursor::new(out)))
+                    }
+                }
+            }
+            Layout::Plain | Layout::StreamCompressed => {
+                // the next marker must also be plausible
+                inner.seek(SeekFrom::Current(
+                    i64::try_from(len).map_err(|_| Error::LengthOverflow)?,
+                ))?;
+                inner.read_exact(&mut marker)?;
+                let next = u64::from_le_bytes(marker);
+                if next > self.max_item_size && marker != footer() {
+                    return Err(not_boundary);
+                }
+                inner.seek(SeekFrom::Start(offset + GLOBAL_MARKER_LEN))?;
+                Ok(Box::new(inner.take(len)))
+            }
+        }
+    }
+}
+
+/// Decompresses item-compressed frames (or copies stored items), as returned by
+/// [`RawExpand::next_raw`], reusing a zstd context
+pub struct FrameExpand<'d> {
+    decompressor: Decompressor<'d>,
+    zstd: DecoderDict<'d>,
+    codec: Codec,
+    stored_items: bool,
+    max_item_size: u64,
+}
+
+impl FrameExpand<'_> {
+    /// decompress a frame into `out`
+    pub fn expand(&mut self, frame: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        let declared = match self.codec {
+            Codec::Zstd if !(self.stored_items && frame.starts_with(&STORED_MAGIC)) => {
+                get_frame_content_size(frame).ok().flatten()
+            }
+            _ => None,
+        };
+        let size = match declared {
+            // larger frames are streamed, so a frame can't make us allocate whatever it declares
+            Some(size) if size <= BULK_LIMIT.min(self.max_item_size) => size,
+            _ => {
+                return self.zstd.decode_item(
+                    self.codec,
+                    self.stored_items,
+                    frame,
+                    self.max_item_size,
+                    out,
+                )
+            }
+        };
+        let size = usize::try_from(size).map_err(|_| Error::LengthOverflow)?;
+        out.clear();
+        out.try_reserve_exact(size)?;
+        if self.decompressor.decompress_to_buffer(frame, out)? != size {
+            return Err(Error::InvalidItem);
+        }
+        Ok(())
+    }
+}
+
+/// the length of an item-compressed frame after decompression, if it declares it
+pub(crate) fn frame_content_len(codec: Codec, stored_items: bool, frame: &[u8]) -> Option<u64> {
+    if stored_items && frame.starts_with(&STORED_MAGIC) {
+        return Some((frame.len() as u64).saturating_sub(STORED_HEADER_LEN));
+    }
+    match codec {
+        Codec::Zstd => get_frame_content_size(frame).ok().flatten(),
+        #[cfg(feature = "lz4")]
+        Codec::Lz4 => frame
+            .first_chunk::<4>()
+            .map(|prefix| u64::from(u32::from_le_bytes(*prefix))),
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+pub(crate) fn read_seekable_header(
+    mut inner: impl Read,
+) -> Result<(Header, Layout, Option<Codec>)> {
+    let mut buf = [0u8; 8];
+    inner.read_exact(&mut buf)?;
+    if stream_wrapper(&buf)?.is_some() {
+        return Err(Error::NotSeekable);
+    }
+    let header = read_header(&buf, &mut inner)?;
+    let (layout, codec) = match header.kind.item_codec()? {
+        Some(codec) => (Layout::ItemCompressed, Some(codec)),
+        None => (Layout::Plain, None),
+    };
+    Ok((header, layout, codec))
+}
+
+impl<R: BufRead> RawExpand<'_, R> {
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// the codec of the stream, for stream-compressed archives, or of each item;
+    /// `None` for streams only wrapped in something archiv can't write, such as gzip
+    pub fn codec(&self) -> Option<Codec> {
+        self.codec
+    }
+
+    /// the (key, value) pairs recorded in the header by `CompressOptions::with_metadata`
+    pub fn archive_metadata(&self) -> &[(String, String)] {
+        &self.metadata
+    }
+
+    /// whether the archive may contain items stored uncompressed, which can only be passed to
+    /// `write_frame` of a writer with [`CompressItem::stored_items`](crate::CompressItem::stored_items)
+    pub fn stored_items(&self) -> bool {
+        self.stored_items
+    }
+
+    /// the archive's trailer, once `check_eof` has read and checked it
+    pub fn trailer(&self) -> Option<Trailer> {
+        self.trailer
+    }
+
+    /// the dictionary used for stream compression, if any
+    pub fn dict_id(&self) -> Option<u32> {
+        self.dict_id
+    }
+
+    /// the offset of the next item (or of the footer, once `next_raw` has returned `None`)
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// the index of the next item (or the number of items, once `next_raw` has returned `None`)
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// read the next item, as stored, into `buf`, returning `None` at a valid footer
+    pub fn next_raw(&mut self, buf: &mut Vec<u8>) -> Result<Option<RawItem>> {
+        let Some(len) = self.read_marker()? else {
+            return Ok(None);
+        };
+
+        buf.clear();
+        buf.try_reserve_exact(usize::try_from(len).map_err(|_| Error::LengthOverflow)?)?;
+        (&mut self.inner).take(len).read_to_end(buf)?;
+        if buf.len() as u64 != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let (content_len, dict_id) = match (self.layout, self.codec) {
+            (Layout::ItemCompressed, Some(Codec::Zstd))
+                if !(self.stored_items && buf.starts_with(&STORED_MAGIC)) =>
+            {
+                (
+                    frame_content_len(Codec::Zstd, false, buf),
+                    get_dict_id_from_frame(buf).map(u32::from),
+                )
+            }
+            (Layout::ItemCompressed, Some(codec)) => {
+                (frame_content_len(codec, self.stored_items, buf), None)
+            }
+            (Layout::ItemCompressed, None) => (None, None),
+            (Layout::Plain | Layout::StreamCompressed, _) => (Some(len), None),
+        };
+        if let Some(summary) = &mut self.summary {
+            summary.item(len, &[buf], content_len);
+        }
+
+        let item = RawItem {
+            index: self.index,
+            offset: self.offset,
+            stored_len: len,
+            content_len,
+            dict_id,
+        };
+        self.advance(len)?;
+        Ok(Some(item))
+    }
+
+    fn read_marker(&mut self) -> Result<Option<u64>> {
+        if self.finished {
+            return Ok(None);
+        }
+        let mut marker = [0u8; 8];
+        self.inner.read_exact(&mut marker)?;
+        let len = u64::from_le_bytes(marker);
+        if len >= MAX_ITEM_SIZE {
+            if marker != footer() {
+                return Err(Error::InvalidItem);
+            }
+            self.finished = true;
+            return Ok(None);
+        }
+        if len > self.max_item_size {
+            return Err(Error::InvalidItem);
+        }
+        Ok(Some(len))
+    }
+
+    fn advance(&mut self, len: u64) -> Result<()> {
+        self.index += 1;
+        self.offset = self
+            .offset
+            .checked_add(GLOBAL_MARKER_LEN + len)
+            .ok_or(Error::LengthOverflow)?;
+        Ok(())
+    }
+
+    /// decompress an item returned by `next_raw` into `out`, if necessary
+    pub fn expand_raw(&self, raw: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        match (self.layout, self.codec) {
+            (Layout::ItemCompressed, Some(codec)) => {
+                self.zstd
+                    .decode_item(codec, self.stored_items, raw, self.max_item_size, out)
+            }
+            (Layout::ItemCompressed, None) => {
+                Err(Error::Internal("item-compressed without a codec"))
+            }
+            (Layout::Plain | Layout::StreamCompressed, _) => {
+                out.clear();
+                out.extend_from_slice(raw);
+                Ok(())
+            }
+        }
+    }
+
+    /// after the footer, check the trailer (if any), and that there's nothing else in the file
+    pub fn check_eof(&mut self) -> Result<()> {
+        if !self.finished {
+            return Err(Error::ApiMisuse);
+        }
+        // archiv writes a stream-compressed archive's trailer after the compressed stream, where
+        // only `ExpandOptions::trailer` can find it
+        let outside = self.layout == Layout::StreamCompressed && self.inner.fill_buf()?.is_empty();
+        if let Some(summary) = self.summary.take().filter(|_| !outside) {
+            let missing = Error::TrailerMissing {
+                offset: self.offset + GLOBAL_MARKER_LEN,
+            };
+            let mut buf = [0u8; TRAILER_LEN as usize];
+            if self.inner.read_exact(&mut buf).is_err() {
+                return Err(missing);
+            }
+            let trailer = Trailer::parse(&buf).ok_or(missing)?;
+            summary.check(&trailer)?;
+            self.trailer = Some(trailer);
+        }
+        if !self.inner.fill_buf()?.is_empty() {
+            return Err(Error::TrailingData);
+        }
+        Ok(())
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R: BufRead + Seek> RawExpand<'_, R> {
+    /// move past the next item without reading it, returning `false` at a valid footer
+    pub fn skip_raw(&mut self) -> Result<bool> {
+        let Some(len) = self.read_marker()? else {
+            return Ok(false);
+        };
+        self.inner
+            .seek_relative(i64::try_from(len).map_err(|_| Error::LengthOverflow)?)?;
+        if let Some(summary) = &mut self.summary {
+            let content_len = match self.layout {
+                Layout::ItemCompressed => None,
+                Layout::Plain | Layout::StreamCompressed => Some(len),
+            };
+            summary.skip(len, content_len);
+        }
+        self.advance(len)?;
+        Ok(true)
+    }
+}
@@ -1,16 +1,26 @@
 use std::io;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use zstd::bulk::Decompressor;
 use zstd::dict::DecoderDictionary;
+use zstd::zstd_safe::{get_dict_id_from_frame, get_frame_content_size};
 
 use crate::error::{Error, Result};
-use crate::header::{parse_header, Kinds, HEADER_TEMPLATE, MAX_ITEM_SIZE, ZSTD_MAGIC};
-use crate::zbuild::DecoderDict;
+use crate::header::{
+    magic_missing, read_header, stored_content, stored_header, Header, Kinds, BZIP2_MAGIC,
+    GZIP_MAGIC, HEADER_TEMPLATE, LZ4_MAGIC, MAX_ITEM_SIZE, STORED_HEADER_LEN, STORED_MAGIC,
+    XZ_MAGIC, ZSTD_MAGIC,
+};
+use crate::seek::SeekTable;
+use crate::zbuild::{Codec, DecoderDict, Wrapper};
 use crate::ZDecoder;
 
 /// Entry point for expansion (reading)
+#[derive(Clone)]
 pub struct ExpandOptions<'d> {
-    max_item_size: u64,
-    zstd: DecoderDict<'d>,
+    pub(crate) max_item_size: u64,
+    pub(crate) zstd: DecoderDict<'d>,
 }
 
 impl Default for ExpandOptions<'static> {
@@ -26,6 +36,21 @@ impl Default for ExpandOptions<'static> {
 /// Trait for reading from compressed streams
 pub trait Expand {
     fn next_item(&mut self) -> Result<Option<Box<dyn Item + '_>>>;
+
+    /// the (key, value) pairs recorded in the header by `CompressOptions::with_metadata`
+    fn archive_metadata(&self) -> &[(String, String)] {
+        &[]
+    }
+}
+
+impl<X: Expand + ?Sized> Expand for Box<X> {
+    fn next_item(&mut self) -> Result<Option<Box<dyn Item + '_>>> {
+        (**self).next_item()
+    }
+
+    fn archive_metadata(&self) -> &[(String, String)] {
+        (**self).archive_metadata()
+    }
 }
 
 pub trait Item: Read {
@@ -39,37 +64,105 @@ pub struct ExpandStream<R> {
     inner: R,
     max_item_size: u64,
     poisoned: bool,
+    progress: Progress,
+    metadata: Vec<(String, String)>,
 }
 
 /// Concrete implementation of the compressed item reader
 pub struct ExpandItem<'d, R> {
     inner: R,
     max_item_size: u64,
+    progress: Progress,
+    metadata: Vec<(String, String)>,
+    stored_items: bool,
     zstd: DecoderDict<'d>,
+    decompressor: Decompressor<'d>,
+    frame: Vec<u8>,
+    out: Vec<u8>,
+}
+
+/// Item reader for codecs other than zstd, which decompresses each item in one go
+struct ExpandBlock<'d, R> {
+    inner: R,
+    max_item_size: u64,
+    progress: Progress,
+    metadata: Vec<(String, String)>,
+    stored_items: bool,
+    zstd: DecoderDict<'d>,
+    codec: Codec,
+    frame: Vec<u8>,
+    out: Vec<u8>,
+}
+
+/// Which item is next, and where it starts, so errors can say where they happened
+struct Progress {
+    /// unknown when reading from part way through an archive
+    index: Option<u64>,
+    /// the bytes consumed from the file: exact, unless the archive is stream-compressed, when the
+    /// decompressor will have read ahead
+    consumed: Arc<AtomicU64>,
+}
+
+/// Where an item started, for errors about it
+#[derive(Copy, Clone)]
+struct Location {
+    index: Option<u64>,
+    offset: u64,
+}
+
+/// The reader returned by `stream_explicit` and `seek_stream`
+pub type ExpandExplicit<'d, R> = ExpandStream<BufReader<ZDecoder<'d, Counting<R>>>>;
+
+/// A seekable stream-compressed archive, returned by `seekable_stream`, whose seek table and
+/// header are only read once
+pub struct SeekStream<'d, R> {
+    opts: ExpandOptions<'d>,
+    inner: R,
+    table: SeekTable,
+    header: Header,
 }
 
+/// Counts the bytes consumed from a file, so errors can say where in the file they happened
+pub struct Counting<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+/// An item which is read lazily, whose errors say which item it was
+struct Located<I> {
+    inner: I,
+    at: Location,
+}
+
+// items up to this size are read in one go, and decompressed in bulk if they declare their size
+pub(crate) const BULK_LIMIT: u64 = 1024 * 1024;
+
+// archives are rarely compressed more than once; nesting is recursive, so is bounded
+const MAX_WRAPPERS: usize = 4;
+
 impl<R: Read> Expand for ExpandStream<R> {
     fn next_item(&mut self) -> Result<Option<Box<dyn Item + '_>>> {
         // this could be a panic, we don't panic in drop to assist with unwinding
         if self.poisoned {
             return Err(Error::ApiMisuse);
         }
-        let mut buf = [0u8; 8];
-        self.inner.read_exact(&mut buf)?;
-        let len = u64::from_le_bytes(buf);
-        // TODO: actually check this is a footer and not just corrupt.
-        if len >= MAX_ITEM_SIZE {
+        let at = self.progress.location();
+        let len = read_len(&mut self.inner, self.max_item_size).map_err(|e| at.wrap(e))?;
+        let Some(len) = len else {
             return Ok(None);
-        }
-        if len > self.max_item_size {
-            return Err(Error::InvalidItem);
-        }
+        };
+        self.progress.advance();
 
         Ok(Some(Box::new(ExpandStreamItem {
             inner: self,
             limit: len,
+            at,
         })))
     }
+
+    fn archive_metadata(&self) -> &[(String, String)] {
+        &self.metadata
+    }
 }
 
 impl<R> ExpandStream<R> {
@@ -86,6 +179,7 @@ impl<R> ExpandStream<R> {
 struct ExpandStreamItem<'i, R> {
     inner: &'i mut ExpandStream<R>,
     limit: u64,
+    at: Location,
 }
 
 impl<R: Read> Item for ExpandStreamItem<'_, R> {
@@ -100,7 +194,11 @@ impl<R: Read> Read for ExpandStreamItem<'_, R> {
             return Ok(0);
         }
         let max = self.limit.min(buf.len() as u64) as usize;
-        let n = self.inner.inner.read(&mut buf[..max])?;
+        let n = self
+            .inner
+            .inner
+            .read(&mut buf[..max])
+            .map_err(|e| self.at.wrap_io(e))?;
         self.limit -= n as u64;
         Ok(n)
     }
@@ -116,101 +214,503 @@ impl<R> Drop for ExpandStreamItem<'_, R> {
 
 impl<R: BufRead> Item for ZDecoder<'_, R> {}
 
+impl<R: Read> Item for io::Take<R> {
+    fn size_hint(&self) -> Option<usize> {
+        usize::try_from(self.limit()).ok()
+    }
+}
+
+impl Item for io::Cursor<&[u8]> {
+    fn size_hint(&self) -> Option<usize> {
+        usize::try_from(self.get_ref().len() as u64 - self.position()).ok()
+    }
+}
+
+impl Item for io::Cursor<Vec<u8>> {
+    fn size_hint(&self) -> Option<usize> {
+        usize::try_from(self.get_ref().len() as u64 - self.position()).ok()
+    }
+}
+
 impl<'d, R: BufRead> Expand for ExpandItem<'d, R> {
     fn next_item(&mut self) -> Result<Option<Box<dyn Item + '_>>> {
-        let mut buf = [0u8; 8];
-        self.inner.read_exact(&mut buf)?;
-        let len = u64::from_le_bytes(buf);
-        // TODO: actually check this is a footer and not just corrupt.
-        if len >= MAX_ITEM_SIZE {
+        let at = self.progress.location();
+        self.read_item(at).map_err(|e| at.wrap(e))
+    }
+
+    fn archive_metadata(&self) -> &[(String, String)] {
+        &self.metadata
+    }
+}
+
+impl<'d, R: BufRead> ExpandItem<'d, R> {
+    fn read_item(&mut self, at: Location) -> Result<Option<Box<dyn Item + '_>>> {
+        let Some(len) = read_len(&mut self.inner, self.max_item_size)? else {
             return Ok(None);
+        };
+        self.progress.advance();
+
+        if len <= BULK_LIMIT {
+            self.frame.clear();
+            (&mut self.inner).take(len).read_to_end(&mut self.frame)?;
+            if self.frame.len() != size_of_len(len)? {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            if let Some(content) = stored_content(&self.frame)?.filter(|_| self.stored_items) {
+                return Ok(Some(Box::new(io::Cursor::new(content))));
+            }
+            let size = match get_frame_content_size(&self.frame) {
+                Ok(Some(size)) if size <= BULK_LIMIT => size_of_len(size)?,
+                _ => {
+                    let inner = self.zstd.decode(&self.frame[..])?;
+                    return Ok(Some(Box::new(Located { inner, at })));
+                }
+            };
+            self.out.clear();
+            self.out.reserve(size);
+            let written = self
+                .decompressor
+                .decompress_to_buffer(&self.frame, &mut self.out)?;
+            if written != size {
+                return Err(Error::InvalidItem);
+            }
+            return Ok(Some(Box::new(io::Cursor::new(&self.out[..]))));
         }
-        if len > self.max_item_size {
-            return Err(Error::InvalidItem);
+
+        // larger items are streamed, once their first few bytes show whether they're stored
+        let mut head = [0u8; STORED_HEADER_LEN as usize];
+        self.inner.read_exact(&mut head)?;
+        let rest = (&mut self.inner).take(len - STORED_HEADER_LEN);
+        if self.stored_items && head.starts_with(&STORED_MAGIC) {
+            if stored_header(len - STORED_HEADER_LEN) != Some(head) {
+                return Err(Error::InvalidItem);
+            }
+            return Ok(Some(Box::new(Located { inner: rest, at })));
         }
+        let inner = self.zstd.decode(io::Cursor::new(head).chain(rest))?;
+        Ok(Some(Box::new(Located { inner, at })))
+    }
+}
 
-        let take = (&mut self.inner).take(len);
-        let decoder = self.zstd.decode(take)?;
-        Ok(Some(Box::new(decoder)))
+impl<R: BufRead> Expand for ExpandBlock<'_, R> {
+    fn next_item(&mut self) -> Result<Option<Box<dyn Item + '_>>> {
+        let at = self.progress.location();
+        self.read_item().map_err(|e| at.wrap(e))
+    }
+
+    fn archive_metadata(&self) -> &[(String, String)] {
+        &self.metadata
     }
 }
 
-impl<'d> ExpandOptions<'d> {
-    pub fn stream<R: BufRead + 'd>(&self, mut inner: R) -> Result<Box<dyn Expand + 'd>> {
-        let hints = inner.fill_buf()?;
-        if hints.is_empty() {
-            return Err(Error::MagicMissing);
+impl<R: BufRead> ExpandBlock<'_, R> {
+    fn read_item(&mut self) -> Result<Option<Box<dyn Item + '_>>> {
+        let Some(len) = read_len(&mut self.inner, self.max_item_size)? else {
+            return Ok(None);
+        };
+        self.progress.advance();
+
+        self.frame.clear();
+        self.frame.try_reserve_exact(size_of_len(len)?)?;
+        (&mut self.inner).take(len).read_to_end(&mut self.frame)?;
+        if self.frame.len() != size_of_len(len)? {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
         }
-        assert_eq!(0x28, ZSTD_MAGIC[0]);
-        assert_eq!(0x29, HEADER_TEMPLATE[0]);
-        match hints[0] {
-            0x28 => {
-                let inner = io::BufReader::new(self.zstd.decode(inner)?);
-                return self.stream(Box::new(inner) as Box<dyn BufRead + '_>);
+        self.zstd.decode_item(
+            self.codec,
+            self.stored_items,
+            &self.frame,
+            self.max_item_size,
+            &mut self.out,
+        )?;
+        Ok(Some(Box::new(io::Cursor::new(&self.out[..]))))
+    }
+}
+
+/// read an item's length marker, returning `None` at the footer
+fn read_len(mut inner: impl Read, max_item_size: u64) -> Result<Option<u64>> {
+    let mut buf = [0u8; 8];
+    inner.read_exact(&mut buf)?;
+    let len = u64::from_le_bytes(buf);
+    // TODO: actually check this is a footer and not just corrupt.
+    if len >= MAX_ITEM_SIZE {
+        return Ok(None);
+    }
+    if len > max_item_size {
+        return Err(Error::InvalidItem);
+    }
+    Ok(Some(len))
+}
+
+impl Progress {
+    fn location(&self) -> Location {
+        Location {
+            index: self.index,
+            offset: self.consumed.load(Ordering::Relaxed),
+        }
+    }
+
+    fn advance(&mut self) {
+        self.index = self.index.map(|index| index + 1);
+    }
+}
+
+impl Location {
+    fn wrap(self, source: Error) -> Error {
+        Error::At {
+            index: self.index,
+            offset: self.offset,
+            source: Box::new(source),
+        }
+    }
+
+    fn wrap_io(self, source: io::Error) -> io::Error {
+        io::Error::new(source.kind(), self.wrap(source.into()))
+    }
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Counting<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.count.fetch_add(amt as u64, Ordering::Relaxed);
+    }
+}
+
+impl<I: Item> Read for Located<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(|e| self.at.wrap_io(e))
+    }
+}
+
+impl<I: Item> Item for Located<I> {
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+/// the stream compression of a file, from its first few bytes, or `None` for a plain archive
+pub(crate) fn stream_wrapper(hints: &[u8]) -> Result<Option<Wrapper>> {
+    assert_eq!(0x28, ZSTD_MAGIC[0]);
+    assert_eq!(0x29, HEADER_TEMPLATE[0]);
+    Ok(Some(match hints.first() {
+        None => return Err(magic_missing(0, hints)),
+        Some(0x28) => Wrapper::Codec(Codec::Zstd),
+        Some(0x29) => return Ok(None),
+        _ if hints.starts_with(&LZ4_MAGIC) => {
+            #[cfg(feature = "lz4")]
+            {
+                Wrapper::Codec(Codec::Lz4)
             }
-            0x29 => (),
-            _ => return Err(Error::MagicMissing),
+            #[cfg(not(feature = "lz4"))]
+            return Err(Error::CodecUnavailable("lz4"));
         }
+        _ if hints.starts_with(&XZ_MAGIC) => {
+            #[cfg(feature = "xz")]
+            {
+                Wrapper::Codec(Codec::Xz)
+            }
+            #[cfg(not(feature = "xz"))]
+            return Err(Error::CodecUnavailable("xz"));
+        }
+        _ if hints.starts_with(&GZIP_MAGIC) => {
+            #[cfg(feature = "gzip")]
+            {
+                Wrapper::Gzip
+            }
+            #[cfg(not(feature = "gzip"))]
+            return Err(Error::CodecUnavailable("gzip"));
+        }
+        _ if hints.starts_with(&BZIP2_MAGIC) => {
+            #[cfg(feature = "bzip2")]
+            {
+                Wrapper::Bzip2
+            }
+            #[cfg(not(feature = "bzip2"))]
+            return Err(Error::CodecUnavailable("bzip2"));
+        }
+        _ => return Err(magic_missing(0, hints)),
+    }))
+}
 
-        let mut buf = [0u8; 8];
-        inner.read_exact(&mut buf)?;
+fn size_of_len(len: u64) -> Result<usize> {
+    usize::try_from(len).map_err(|_| Error::LengthOverflow)
+}
+
+/// An archive with any stream compression removed, positioned after the header
+pub(crate) struct Opened<'d> {
+    pub header: Header,
+    /// the innermost stream compression, if any, ignoring wrappers which can't be written
+    pub stream_codec: Option<Codec>,
+    /// whether there was any stream compression, including wrappers
+    pub wrapped: bool,
+    /// the dictionary used for the outermost stream compression, if any
+    pub dict_id: Option<u32>,
+    pub inner: Box<dyn BufRead + 'd>,
+}
+
+impl<'d> ExpandOptions<'d> {
+    pub fn stream<R: BufRead + 'd>(&self, inner: R) -> Result<Box<dyn Expand + 'd>> {
+        let count = Arc::new(AtomicU64::new(0));
+        let inner = Counting {
+            inner,
+            count: Arc::clone(&count),
+        };
+        let Opened { header, inner, .. } = self.open(Box::new(inner))?;
+        let metadata = header.metadata()?;
+        let stored_items = header.has_stored_items();
+        let progress = Progress {
+            index: Some(0),
+            consumed: count,
+        };
         let max_item_size = self.max_item_size;
-        let kind = parse_header(&buf)?;
-        Ok(match kind {
-            Kinds::Plain => Box::new(ExpandStream {
+        Ok(match header.kind.item_codec()? {
+            None => Box::new(ExpandStream {
                 inner,
                 max_item_size,
                 poisoned: false,
+                progress,
+                metadata,
             }),
-            Kinds::ItemCompressed => Box::new(ExpandItem {
+            Some(Codec::Zstd) => Box::new(ExpandItem {
                 inner,
                 max_item_size,
+                progress,
+                metadata,
+                stored_items,
                 zstd: self.zstd.clone(),
+                decompressor: self.zstd.decompressor()?,
+                frame: Vec::new(),
+                out: Vec::new(),
+            }),
+            #[allow(unreachable_patterns)]
+            Some(codec) => Box::new(ExpandBlock {
+                inner,
+                max_item_size,
+                progress,
+                metadata,
+                stored_items,
+                zstd: self.zstd.clone(),
+                codec,
+                frame: Vec::new(),
+                out: Vec::new(),
             }),
         })
     }
 
+    pub(crate) fn open(&self, inner: Box<dyn BufRead + 'd>) -> Result<Opened<'d>> {
+        self.open_wrapped(inner, 0)
+    }
+
+    // `depth` is the number of wrappers already removed
+    fn open_wrapped(&self, mut inner: Box<dyn BufRead + 'd>, depth: usize) -> Result<Opened<'d>> {
+        let hints = inner.fill_buf()?;
+        if let Some(wrapper) = stream_wrapper(hints)? {
+            if depth >= MAX_WRAPPERS {
+                return Err(Error::TooManyWrappers { max: MAX_WRAPPERS });
+            }
+            let codec = match wrapper {
+                Wrapper::Codec(codec) => Some(codec),
+                #[allow(unreachable_patterns)]
+                _ => None,
+            };
+            let dict_id = match codec {
+                Some(Codec::Zstd) => get_dict_id_from_frame(hints).map(u32::from),
+                _ => None,
+            };
+            let inner = io::BufReader::new(self.zstd.decode_stream(wrapper, inner)?);
+            let opened = self
+                .open_wrapped(Box::new(inner), depth + 1)
+                .map_err(|e| e.inside(wrapper.name()))?;
+            return Ok(Opened {
+                stream_codec: opened.stream_codec.or(codec),
+                wrapped: true,
+                dict_id: opened.dict_id.or(dict_id),
+                ..opened
+            });
+        }
+
+        let mut buf = [0u8; 8];
+        inner.read_exact(&mut buf)?;
+        Ok(Opened {
+            header: read_header(&buf, &mut inner)?,
+            stream_codec: None,
+            wrapped: false,
+            dict_id: None,
+            inner,
+        })
+    }
+
     /// open a stream that is known to be compressed, without returning traits
-    pub fn stream_explicit<R: BufRead + 'd>(
+    ///
+    /// Error offsets are how far into the file the decompressor had read, which is ahead of the
+    /// item which failed.
+    pub fn stream_explicit<R: BufRead + 'd>(&self, inner: R) -> Result<ExpandExplicit<'d, R>> {
+        let count = Arc::new(AtomicU64::new(0));
+        let inner = Counting {
+            inner,
+            count: Arc::clone(&count),
+        };
+        let (header, inner) = self.stream_header(inner)?;
+        Ok(ExpandStream {
+            inner,
+            max_item_size: self.max_item_size,
+            poisoned: false,
+            progress: Progress {
+                index: Some(0),
+                consumed: count,
+            },
+            metadata: header.metadata()?,
+        })
+    }
+
+    /// the header of a zstd stream-compressed archive, and the rest of the stream
+    pub(crate) fn stream_header<R: BufRead>(
         &self,
         mut inner: R,
-    ) -> Result<ExpandStream<BufReader<ZDecoder<'d, R>>>> {
+    ) -> Result<(Header, BufReader<ZDecoder<'d, R>>)> {
         let hints = inner.fill_buf()?;
         if hints.is_empty() {
-            return Err(Error::MagicMissing);
+            return Err(magic_missing(0, hints));
         }
         assert_eq!(0x28, ZSTD_MAGIC[0]);
         assert_eq!(0x29, HEADER_TEMPLATE[0]);
         let mut inner = match hints[0] {
             0x28 => io::BufReader::new(self.zstd.decode(inner)?),
-            _ => return Err(Error::MagicMissing),
+            _ => return Err(magic_missing(0, hints)),
         };
 
         let mut buf = [0u8; 8];
         inner.read_exact(&mut buf)?;
-        let max_item_size = self.max_item_size;
-        let kind = parse_header(&buf)?;
-        match kind {
-            Kinds::Plain => Ok(ExpandStream {
-                inner,
-                max_item_size,
-                poisoned: false,
+        let header = read_header(&buf, &mut inner)?;
+        match header.kind {
+            Kinds::Plain => Ok((header, inner)),
+            _ => Err(Error::MagicMissing {
+                offset: 0,
+                found: buf.to_vec(),
+                guess: Some("an item-compressed archiv, inside zstd".to_string()),
             }),
-            _ => Err(Error::MagicMissing)?,
         }
     }
+
+    /// open a seekable stream-compressed archive at an item, by the offset returned from
+    /// `write_item_vectored`
+    ///
+    /// As with `stream_explicit`, error offsets are how far into the file the decompressor had
+    /// read; item indexes are unknown, unless `offset` is zero.
+    ///
+    /// The archive's header is read for every call; [`seekable_stream`](Self::seekable_stream)
+    /// reads it once, for repeated seeks.
+    pub fn seek_stream<R: Read + Seek + 'd>(
+        &self,
+        mut inner: R,
+        table: &SeekTable,
+        offset: u64,
+    ) -> Result<ExpandExplicit<'d, BufReader<R>>> {
+        // the header, and its metadata, is only at the start
+        inner.seek(SeekFrom::Start(0))?;
+        let (header, _) = self.stream_header(BufReader::new(&mut inner))?;
+        self.seek_frame(inner, table, &header, offset)
+    }
+
+    /// open a seekable stream-compressed archive, reading its seek table and header, to then
+    /// [`seek`](SeekStream::seek) to any number of items
+    pub fn seekable_stream<R: Read + Seek>(&self, mut inner: R) -> Result<SeekStream<'d, R>> {
+        let table = SeekTable::read_from(&mut inner)?;
+        inner.seek(SeekFrom::Start(0))?;
+        let (header, _) = self.stream_header(BufReader::new(&mut inner))?;
+        Ok(SeekStream {
+            opts: self.clone(),
+            inner,
+            table,
+            header,
+        })
+    }
+
+    fn seek_frame<R: Read + Seek>(
+        &self,
+        mut inner: R,
+        table: &SeekTable,
+        header: &Header,
+        offset: u64,
+    ) -> Result<ExpandExplicit<'d, BufReader<R>>> {
+        // zero is the first item, wherever the header ends
+        let (offset, index) = match offset {
+            0 => (header.encoded_len(), Some(0)),
+            _ => (offset, None),
+        };
+        if offset < header.encoded_len() {
+            return Err(Error::ApiMisuse);
+        }
+        let frame = table.frame_for(offset).ok_or(Error::ApiMisuse)?;
+        inner.seek(SeekFrom::Start(frame.compressed_offset))?;
+        let count = Arc::new(AtomicU64::new(frame.compressed_offset));
+        let inner = Counting {
+            inner: BufReader::new(inner),
+            count: Arc::clone(&count),
+        };
+        let mut inner = BufReader::new(self.zstd.decode(inner)?);
+        let skip = offset - frame.decompressed_offset;
+        if io::copy(&mut (&mut inner).take(skip), &mut io::sink())? != skip {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(ExpandStream {
+            inner,
+            max_item_size: self.max_item_size,
+            poisoned: false,
+            progress: Progress {
+                index,
+                consumed: count,
+            },
+            metadata: header.metadata()?,
+        })
+    }
+}
+
+impl<'d, R: Read + Seek> SeekStream<'d, R> {
+    /// read from the item at an offset returned by `write_item_vectored`, as `seek_stream` does
+    pub fn seek(&mut self, offset: u64) -> Result<ExpandExplicit<'d, BufReader<&mut R>>> {
+        self.opts
+            .seek_frame(&mut self.inner, &self.table, &self.header, offset)
+    }
+
+    pub fn table(&self) -> &SeekTable {
+        &self.table
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
 }
 
 impl<'d> ExpandOptions<'d> {
     #[must_use]
     pub fn without_dict(mut self) -> Self {
-        self.zstd = DecoderDict::None;
+        self.zstd.dict = None;
         self
     }
 
     #[must_use]
     pub fn with_dict(mut self, dict: &'d DecoderDictionary<'static>) -> Self {
-        self.zstd = DecoderDict::Dict(dict);
+        self.zstd.dict = Some(dict);
+        self
+    }
+
+    /// Accept zstd windows up to this size (as a power of two), if the writer used a large window log
+    #[must_use]
+    pub fn with_window_log_max(mut self, val: u32) -> Self {
+        self.zstd.window_log_max = Some(val);
         self
     }
 }
@@ -0,0 +1,195 @@
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::error::{Error, Result};
+
+// https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md
+const SKIPPABLE_MAGIC: u32 = 0x184d_2a5e;
+const SEEKABLE_MAGIC: u32 = 0x8f92_eab1;
+const SKIPPABLE_HEADER_LEN: u64 = 8;
+const SEEK_TABLE_FOOTER_LEN: u64 = 9;
+const CHECKSUM_FLAG: u8 = 0x80;
+
+// the table records frame sizes as u32s; this leaves room for incompressible data to grow a little
+// when compressed, and for the footer and trailer in the last frame
+pub const MAX_FRAME_BYTES: u64 = 0xf000_0000;
+
+/// Frame limits for seekable stream compression; a frame is cut after the item which reaches either.
+#[derive(Copy, Clone, Default)]
+pub struct FrameLimits {
+    pub bytes: Option<u64>,
+    pub items: Option<u64>,
+}
+
+/// The locations of the zstd frames in a seekable stream-compressed archive
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SeekTable {
+    frames: Vec<SeekFrame>,
+}
+
+/// One zstd frame of a seekable archive; offsets are from the start of the file (compressed),
+/// or the start of the archive (decompressed), as returned by `write_item_vectored`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SeekFrame {
+    pub compressed_offset: u64,
+    pub compressed_size: u64,
+    pub decompressed_offset: u64,
+    pub decompressed_size: u64,
+}
+
+impl SeekTable {
+    pub fn frames(&self) -> &[SeekFrame] {
+        &self.frames
+    }
+
+    /// The frame containing this decompressed offset
+    pub fn frame_for(&self, decompressed_offset: u64) -> Option<&SeekFrame> {
+        let idx = self.frames.partition_point(|f| {
+            f.decompressed_offset + f.decompressed_size <= decompressed_offset
+        });
+        self.frames.get(idx)
+    }
+
+    pub(crate) fn push(&mut self, compressed_size: u64, decompressed_size: u64) {
+        let (compressed_offset, decompressed_offset) = match self.frames.last() {
+            Some(f) => (
+                f.compressed_offset + f.compressed_size,
+                f.decompressed_offset + f.decompressed_size,
+            ),
+            None => (0, 0),
+        };
+        self.frames.push(SeekFrame {
+            compressed_offset,
+            compressed_size,
+            decompressed_offset,
+            decompressed_size,
+        });
+    }
+
+    /// the length of the compressed frames, i.e. where the table's skippable frame, or anything
+    /// else between the frames and the table, starts
+    pub(crate) fn compressed_len(&self) -> u64 {
+        self.frames
+            .last()
+            .map_or(0, |f| f.compressed_offset + f.compressed_size)
+    }
+
+    /// whether a file ends with a seek table, which may still be damaged
+    pub(crate) fn present<R: Read + Seek>(mut r: R) -> Result<bool> {
+        if r.seek(SeekFrom::End(0))? < SEEK_TABLE_FOOTER_LEN {
+            return Ok(false);
+        }
+        r.seek(SeekFrom::End(-4))?;
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        Ok(u32::from_le_bytes(magic) == SEEKABLE_MAGIC)
+    }
+
+    /// Write the table as a zstd skippable frame
+    pub(crate) fn write_to(&self, mut w: impl Write) -> Result<()> {
+        let num_frames = u32::try_from(self.frames.len()).map_err(|_| Error::LengthOverflow)?;
+        let entries_len =
+            u32::try_from(self.frames.len() * 8).map_err(|_| Error::LengthOverflow)?;
+        let frame_size = entries_len
+            .checked_add(SEEK_TABLE_FOOTER_LEN as u32)
+            .ok_or(Error::LengthOverflow)?;
+
+        let mut buf = Vec::with_capacity(SKIPPABLE_HEADER_LEN as usize + frame_size as usize);
+        buf.extend_from_slice(&SKIPPABLE_MAGIC.to_le_bytes());
+        buf.extend_from_slice(&frame_size.to_le_bytes());
+        for frame in &self.frames {
+            let compressed =
+                u32::try_from(frame.compressed_size).map_err(|_| Error::LengthOverflow)?;
+            let decompressed =
+                u32::try_from(frame.decompressed_size).map_err(|_| Error::LengthOverflow)?;
+            buf.extend_from_slice(&compressed.to_le_bytes());
+            buf.extend_from_slice(&decompressed.to_le_bytes());
+        }
+        buf.extend_from_slice(&num_frames.to_le_bytes());
+        buf.push(0);
+        buf.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+        w.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Read the table from the end of a seekable stream-compressed archive
+    pub fn read_from<R: Read + Seek>(mut r: R) -> Result<SeekTable> {
+        let mut footer = [0u8; SEEK_TABLE_FOOTER_LEN as usize];
+        let footer_offset = r.seek(SeekFrom::End(-(SEEK_TABLE_FOOTER_LEN as i64)))?;
+        r.read_exact(&mut footer)?;
+        let num_frames = u32::from_le_bytes(footer[..4].try_into().expect("fixed slice"));
+        let descriptor = footer[4];
+        if u32::from_le_bytes(footer[5..].try_into().expect("fixed slice")) != SEEKABLE_MAGIC {
+            return Err(Error::MagicMissing {
+                offset: footer_offset + 5,
+                found: footer[5..].to_vec(),
+                guess: None,
+            });
+        }
+        if descriptor & !CHECKSUM_FLAG != 0 {
+            return Err(Error::MagicUnrecognised {
+                offset: footer_offset + 4,
+                what: "seek table descriptor",
+                value: descriptor,
+            });
+        }
+        let entry_len: u64 = if descriptor & CHECKSUM_FLAG != 0 {
+            12
+        } else {
+            8
+        };
+        let entries_len = u64::from(num_frames) * entry_len;
+
+        let table_len = SKIPPABLE_HEADER_LEN + entries_len + SEEK_TABLE_FOOTER_LEN;
+        let table_offset = r.seek(SeekFrom::End(
+            -i64::try_from(table_len).map_err(|_| Error::LengthOverflow)?,
+        ))?;
+        let mut header = [0u8; SKIPPABLE_HEADER_LEN as usize];
+        r.read_exact(&mut header)?;
+        if u32::from_le_bytes(header[..4].try_into().expect("fixed slice")) != SKIPPABLE_MAGIC
+            || u64::from(u32::from_le_bytes(
+                header[4..].try_into().expect("fixed slice"),
+            )) != entries_len + SEEK_TABLE_FOOTER_LEN
+        {
+            return Err(Error::MagicMissing {
+                offset: table_offset,
+                found: header.to_vec(),
+                guess: None,
+            });
+        }
+
+        let mut entries = Vec::new();
+        entries
+            .try_reserve_exact(usize::try_from(entries_len).map_err(|_| Error::LengthOverflow)?)?;
+        (&mut r).take(entries_len).read_to_end(&mut entries)?;
+        if entries.len() as u64 != entries_len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let mut table = SeekTable::default();
+        for entry in entries.chunks_exact(entry_len as usize) {
+            let compressed = u32::from_le_bytes(entry[..4].try_into().expect("fixed slice"));
+            let decompressed = u32::from_le_bytes(entry[4..8].try_into().expect("fixed slice"));
+            table.push(u64::from(compressed), u64::from(decompressed));
+        }
+        Ok(table)
+    }
+}
+
+/// Writer which tracks how many bytes have passed through it
+pub(crate) struct Counted<W> {
+    pub inner: W,
+    pub count: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
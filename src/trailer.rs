@@ -0,0 +1,231 @@
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use xxhash_rust::xxh64::Xxh64;
+
+use crate::error::{Error, Result};
+use crate::header::{footer, GLOBAL_MARKER_LEN, ZSTD_MAGIC};
+use crate::raw::read_seekable_header;
+use crate::seek::SeekTable;
+use crate::ExpandOptions;
+
+const TRAILER_MAGIC: [u8; 8] = *b"\x29\xb6arcend";
+// items, uncompressed bytes, stored bytes, checksum, then the magic
+pub const TRAILER_LEN: u64 = 5 * 8;
+const UNKNOWN: u64 = u64::MAX;
+
+// after a stream-compressed archive, the trailer is in a zstd skippable frame, outside the
+// compressed stream (and before any seek table), so it can be found without decompressing
+const FRAME_MAGIC: [u8; 4] = *b"\x5f\x2a\x4d\x18";
+pub const TRAILER_FRAME_LEN: u64 = 8 + TRAILER_LEN;
+
+/// The summary written after the footer (or the compressed stream) by [`CompressOptions::with_trailer`](crate::CompressOptions::with_trailer)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Trailer {
+    pub items: u64,
+    /// the total length of the items, or `None` if frames without a declared size were written
+    /// with `write_frame`
+    pub uncompressed_bytes: Option<u64>,
+    /// the total length of the items as stored, i.e. after item compression
+    pub stored_bytes: u64,
+    /// the xxh64 of the archive up to the trailer (header, items and footer), before any stream
+    /// compression
+    pub checksum: u64,
+}
+
+impl Trailer {
+    pub(crate) fn to_bytes(self) -> [u8; TRAILER_LEN as usize] {
+        let mut buf = [0u8; TRAILER_LEN as usize];
+        let fields = [
+            self.items,
+            self.uncompressed_bytes.unwrap_or(UNKNOWN),
+            self.stored_bytes,
+            self.checksum,
+        ];
+        for (chunk, field) in buf.chunks_exact_mut(8).zip(fields) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        buf[32..].copy_from_slice(&TRAILER_MAGIC);
+        buf
+    }
+
+    pub(crate) fn to_frame(self) -> [u8; TRAILER_FRAME_LEN as usize] {
+        let mut buf = [0u8; TRAILER_FRAME_LEN as usize];
+        buf[..4].copy_from_slice(&FRAME_MAGIC);
+        buf[4..8].copy_from_slice(&(TRAILER_LEN as u32).to_le_bytes());
+        buf[8..].copy_from_slice(&self.to_bytes());
+        buf
+    }
+
+    /// the trailer, if `buf` is one
+    pub(crate) fn parse(buf: &[u8; TRAILER_LEN as usize]) -> Option<Trailer> {
+        if buf[32..] != TRAILER_MAGIC {
+            return None;
+        }
+        let field = |i: usize| {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&buf[i * 8..(i + 1) * 8]);
+            u64::from_le_bytes(word)
+        };
+        Some(Trailer {
+            items: field(0),
+            uncompressed_bytes: Some(field(1)).filter(|&v| v != UNKNOWN),
+            stored_bytes: field(2),
+            checksum: field(3),
+        })
+    }
+}
+
+/// The running totals for a trailer, as an archive is written or read
+pub(crate) struct Summary {
+    items: u64,
+    uncompressed: Option<u64>,
+    stored: u64,
+    // absent if any items were skipped, instead of read
+    hash: Option<Xxh64>,
+}
+
+impl Summary {
+    pub fn new(header: &[u8]) -> Summary {
+        let mut hash = Xxh64::new(0);
+        hash.update(header);
+        Summary {
+            items: 0,
+            uncompressed: Some(0),
+            stored: 0,
+            hash: Some(hash),
+        }
+    }
+
+    /// an item of `len` bytes, as stored, made up of `data`
+    pub fn item(&mut self, len: u64, data: &[&[u8]], content_len: Option<u64>) {
+        if let Some(hash) = &mut self.hash {
+            hash.update(&len.to_le_bytes());
+            for slice in data {
+                hash.update(slice);
+            }
+        }
+        self.count(len, content_len);
+    }
+
+    /// an item which wasn't read, so the checksum can't be checked
+    pub fn skip(&mut self, len: u64, content_len: Option<u64>) {
+        self.hash = None;
+        self.count(len, content_len);
+    }
+
+    fn count(&mut self, len: u64, content_len: Option<u64>) {
+        self.items += 1;
+        self.stored = self.stored.saturating_add(len);
+        self.uncompressed = self
+            .uncompressed
+            .zip(content_len)
+            .and_then(|(total, len)| total.checked_add(len));
+    }
+
+    pub fn finish(self) -> Trailer {
+        let mut hash = self.hash.unwrap_or_else(|| Xxh64::new(0));
+        hash.update(&footer());
+        Trailer {
+            items: self.items,
+            uncompressed_bytes: self.uncompressed,
+            stored_bytes: self.stored,
+            checksum: hash.digest(),
+        }
+    }
+
+    /// check a trailer read from the archive against what was read
+    pub fn check(self, recorded: &Trailer) -> Result<()> {
+        let hashed = self.hash.is_some();
+        let actual = self.finish();
+        let mismatch = |what, recorded, actual| {
+            Err(Error::TrailerMismatch {
+                what,
+                recorded,
+                actual,
+            })
+        };
+        if recorded.items != actual.items {
+            return mismatch("item count", recorded.items, actual.items);
+        }
+        if recorded.stored_bytes != actual.stored_bytes {
+            return mismatch("stored bytes", recorded.stored_bytes, actual.stored_bytes);
+        }
+        if let (Some(recorded), Some(actual)) =
+            (recorded.uncompressed_bytes, actual.uncompressed_bytes)
+            && recorded != actual
+        {
+            return mismatch("uncompressed bytes", recorded, actual);
+        }
+        if hashed && recorded.checksum != actual.checksum {
+            return mismatch("checksum", recorded.checksum, actual.checksum);
+        }
+        Ok(())
+    }
+}
+
+impl ExpandOptions<'_> {
+    /// read the trailer from the end of an archive, without reading the items; `None` if the
+    /// archive was written without one
+    ///
+    /// A missing trailer, when the header says there should be one, suggests the file was truncated.
+    /// Stream-compressed archives must be zstd, as archiv writes them.
+    pub fn trailer<R: Read + Seek>(&self, mut inner: R) -> Result<Option<Trailer>> {
+        inner.seek(SeekFrom::Start(0))?;
+        let header = match read_seekable_header(&mut inner) {
+            Ok((header, _, _)) => header,
+            Err(Error::NotSeekable) => return self.stream_trailer(inner),
+            Err(e) => return Err(e),
+        };
+        if !header.has_trailer() {
+            return Ok(None);
+        }
+        let file_len = inner.seek(SeekFrom::End(0))?;
+        let offset = file_len.saturating_sub(GLOBAL_MARKER_LEN + TRAILER_LEN);
+        let missing = Error::TrailerMissing { offset };
+        if offset < header.encoded_len() {
+            return Err(missing);
+        }
+        inner.seek(SeekFrom::Start(offset))?;
+        let mut marker = [0u8; GLOBAL_MARKER_LEN as usize];
+        inner.read_exact(&mut marker)?;
+        let mut buf = [0u8; TRAILER_LEN as usize];
+        inner.read_exact(&mut buf)?;
+        match Trailer::parse(&buf) {
+            Some(trailer) if marker == footer() => Ok(Some(trailer)),
+            _ => Err(missing),
+        }
+    }
+
+    /// the trailer frame, after the zstd frames of a stream-compressed archive
+    fn stream_trailer<R: Read + Seek>(&self, mut inner: R) -> Result<Option<Trailer>> {
+        inner.seek(SeekFrom::Start(0))?;
+        let mut magic = [0u8; 4];
+        inner.read_exact(&mut magic)?;
+        if magic != ZSTD_MAGIC {
+            return Err(Error::NotSeekable);
+        }
+        inner.seek(SeekFrom::Start(0))?;
+        let (header, _) = self.stream_header(BufReader::new(&mut inner))?;
+        if !header.has_trailer() {
+            return Ok(None);
+        }
+        let offset = if SeekTable::present(&mut inner)? {
+            SeekTable::read_from(&mut inner)?.compressed_len()
+        } else {
+            let file_len = inner.seek(SeekFrom::End(0))?;
+            file_len.saturating_sub(TRAILER_FRAME_LEN)
+        };
+        let missing = Error::TrailerMissing { offset };
+        inner.seek(SeekFrom::Start(offset))?;
+        let mut buf = [0u8; TRAILER_FRAME_LEN as usize];
+        if inner.read_exact(&mut buf).is_err() || buf[..4] != FRAME_MAGIC {
+            return Err(missing);
+        }
+        let len = u32::from_le_bytes(buf[4..8].try_into().expect("fixed slice"));
+        let trailer = buf[8..].try_into().ok().and_then(Trailer::parse);
+        match trailer {
+            Some(trailer) if u64::from(len) == TRAILER_LEN => Ok(Some(trailer)),
+            _ => Err(missing),
+        }
+    }
+}
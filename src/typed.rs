@@ -0,0 +1,168 @@
+use std::io::Read;
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::{Compress, Expand};
+
+/// How typed items are turned into bytes, and back again
+pub trait Encoding {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T, out: &mut Vec<u8>) -> Result<()>;
+    fn decode<T: DeserializeOwned>(&self, item: &[u8]) -> Result<T>;
+}
+
+/// Items as JSON documents
+#[cfg(feature = "json")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Json;
+
+/// Items as CBOR
+#[cfg(feature = "cbor")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Cbor;
+
+/// Items in postcard's compact, non-self-describing format
+#[cfg(feature = "postcard")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Postcard;
+
+impl Error {
+    /// an error from encoding or decoding an item, for implementations of [`Encoding`]
+    pub fn encoding(source: impl std::error::Error + Send + Sync + 'static) -> Error {
+        Error::Encoding {
+            source: Box::new(source),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl Encoding for Json {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T, out: &mut Vec<u8>) -> Result<()> {
+        serde_json::to_writer(out, value).map_err(Error::encoding)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, item: &[u8]) -> Result<T> {
+        serde_json::from_slice(item).map_err(Error::encoding)
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl Encoding for Cbor {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T, out: &mut Vec<u8>) -> Result<()> {
+        ciborium::into_writer(value, out).map_err(Error::encoding)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, item: &[u8]) -> Result<T> {
+        ciborium::from_reader(item).map_err(Error::encoding)
+    }
+}
+
+#[cfg(feature = "postcard")]
+impl Encoding for Postcard {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T, out: &mut Vec<u8>) -> Result<()> {
+        let encoded = postcard::to_extend(value, std::mem::take(out)).map_err(Error::encoding)?;
+        *out = encoded;
+        Ok(())
+    }
+
+    fn decode<T: DeserializeOwned>(&self, item: &[u8]) -> Result<T> {
+        postcard::from_bytes(item).map_err(Error::encoding)
+    }
+}
+
+/// Writes values of type `T` as items, encoded with `E`
+pub struct TypedCompress<T: ?Sized, E, C> {
+    inner: C,
+    encoding: E,
+    buf: Vec<u8>,
+    _items: PhantomData<fn(&T)>,
+}
+
+impl<T: Serialize + ?Sized, E: Encoding, C> TypedCompress<T, E, C> {
+    pub fn new(inner: C, encoding: E) -> Self {
+        TypedCompress {
+            inner,
+            encoding,
+            buf: Vec::new(),
+            _items: PhantomData,
+        }
+    }
+
+    /// Append a value to the writer, returning the offset of the item, as `write_item_vectored` does
+    pub fn write<W>(&mut self, value: &T) -> Result<u64>
+    where
+        C: Compress<W>,
+    {
+        self.buf.clear();
+        self.encoding.encode(value, &mut self.buf)?;
+        self.inner.write_item_vectored(&[&self.buf])
+    }
+
+    /// Complete the writer
+    pub fn finish<W>(self) -> Result<W>
+    where
+        C: Compress<W>,
+    {
+        self.inner.finish()
+    }
+
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+/// Reads items as values of type `T`, encoded with `E`; an iterator of the values
+pub struct TypedExpand<T, E, X> {
+    inner: X,
+    encoding: E,
+    buf: Vec<u8>,
+    _items: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned, E: Encoding, X: Expand> TypedExpand<T, E, X> {
+    pub fn new(inner: X, encoding: E) -> Self {
+        TypedExpand {
+            inner,
+            encoding,
+            buf: Vec::new(),
+            _items: PhantomData,
+        }
+    }
+
+    /// the next value, or `None` at the end of the archive
+    pub fn next_value(&mut self) -> Result<Option<T>> {
+        let Some(mut item) = self.inner.next_item()? else {
+            return Ok(None);
+        };
+        self.buf.clear();
+        item.read_to_end(&mut self.buf)?;
+        self.encoding.decode(&self.buf).map(Some)
+    }
+
+    /// the (key, value) pairs recorded in the header by `CompressOptions::with_metadata`
+    pub fn archive_metadata(&self) -> &[(String, String)] {
+        self.inner.archive_metadata()
+    }
+
+    pub fn get_mut(&mut self) -> &mut X {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> X {
+        self.inner
+    }
+}
+
+impl<T: DeserializeOwned, E: Encoding, X: Expand> Iterator for TypedExpand<T, E, X> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        self.next_value().transpose()
+    }
+}
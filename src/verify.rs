@@ -0,0 +1,104 @@
+use std::io::BufRead;
+
+use crate::error::{Error, Result};
+use crate::header::GLOBAL_MARKER_LEN;
+use crate::raw::{Layout, RawExpand};
+use crate::ExpandOptions;
+
+/// The result of checking an entire archive with [`ExpandOptions::verify`]
+#[derive(Debug)]
+pub struct VerifyReport {
+    /// the layout, if the header could be read
+    pub layout: Option<Layout>,
+    /// the number of items which were read successfully
+    pub items: u64,
+    /// the first problem found, if any
+    pub failure: Option<VerifyFailure>,
+}
+
+/// Where, and why, an archive failed verification
+#[derive(Debug)]
+pub struct VerifyFailure {
+    /// the index of the item which failed; the item count for problems with the footer
+    pub index: u64,
+    /// the offset of the item (or footer) which failed, in the same terms as
+    /// `write_item_vectored`
+    pub offset: u64,
+    pub error: Error,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+impl<'d> ExpandOptions<'d> {
+    /// read and decompress every item in an archive, checking the header, lengths, frames and footer
+    pub fn verify<R: BufRead + 'd>(&self, inner: R) -> VerifyReport {
+        let mut report = VerifyReport {
+            layout: None,
+            items: 0,
+            failure: None,
+        };
+        let mut archiv = match self.raw(inner) {
+            Ok(archiv) => archiv,
+            Err(error) => {
+                report.failure = Some(VerifyFailure {
+                    index: 0,
+                    offset: 0,
+                    error,
+                });
+                return report;
+            }
+        };
+        report.layout = Some(archiv.layout());
+
+        let mut raw = Vec::with_capacity(4096);
+        let mut out = Vec::with_capacity(4096);
+        loop {
+            let index = archiv.index();
+            let offset = archiv.offset();
+            match next_checked(&mut archiv, &mut raw, &mut out) {
+                Ok(true) => report.items += 1,
+                Ok(false) => break,
+                Err(error) => {
+                    report.failure = Some(VerifyFailure {
+                        index,
+                        offset,
+                        error,
+                    });
+                    return report;
+                }
+            }
+        }
+
+        if let Err(error) = archiv.check_eof() {
+            report.failure = Some(VerifyFailure {
+                index: archiv.index(),
+                offset: archiv.offset() + GLOBAL_MARKER_LEN,
+                error,
+            });
+        }
+        report
+    }
+}
+
+/// read and expand the next item, returning `false` at the footer
+fn next_checked<R: BufRead>(
+    archiv: &mut RawExpand<'_, R>,
+    raw: &mut Vec<u8>,
+    out: &mut Vec<u8>,
+) -> Result<bool> {
+    let Some(item) = archiv.next_raw(raw)? else {
+        return Ok(false);
+    };
+    archiv.expand_raw(raw, out)?;
+    let actual = out.len() as u64;
+    match item.content_len {
+        Some(declared) if declared != actual => {
+            Err(Error::ContentSizeMismatch { declared, actual })
+        }
+        _ => Ok(true),
+    }
+}
@@ -1,14 +1,25 @@
 use std::io::Write;
 use zstd::dict::EncoderDictionary;
+use zstd::zstd_safe::find_frame_compressed_size;
 
 use crate::error::{Error, Result};
-use crate::header::{footer, header, Kinds, GLOBAL_MARKER_LEN};
-use crate::zbuild::EncoderDict;
+use crate::header::{
+    footer, stored_content, stored_header, Header, Kinds, GLOBAL_MARKER_LEN, STORED_HEADER_LEN,
+    ZSTD_MAGIC,
+};
+use crate::raw::frame_content_len;
+use crate::seek::{Counted, FrameLimits, SeekTable, MAX_FRAME_BYTES};
+use crate::trailer::Summary;
+use crate::zbuild::{Codec, Dict, Encoder, EncoderDict, ItemCompressor};
 
 /// Entry point for compression (writing)
 #[derive(Default)]
 pub struct CompressOptions<'d> {
     zstd: EncoderDict<'d>,
+    frames: Option<FrameLimits>,
+    metadata: Vec<(String, String)>,
+    trailer: bool,
+    stored_items: bool,
 }
 
 /// Trait for writing compressed streams
@@ -16,6 +27,10 @@ pub trait Compress<W> {
     /// Append an item to the writer
     fn write_item(&mut self, item: &[u8]) -> Result<u64>;
 
+    /// Append an item, made up of the concatenation of the slices, to the writer, returning the
+    /// offset where the item starts
+    fn write_item_vectored(&mut self, item: &[&[u8]]) -> Result<u64>;
+
     /// Complete the writer
     fn finish(self) -> Result<W>;
 
@@ -26,87 +41,235 @@ pub trait Compress<W> {
 /// Concrete implementation of the compressed stream writer
 pub struct CompressStream<'e, W: Write> {
     off: u64,
-    inner: zstd::Encoder<'e, W>,
+    // what `write_item` has always returned: `off`, without the length markers of items written
+    // by `write_item`
+    item_off: u64,
+    // only absent while a frame is being cut
+    inner: Option<Encoder<'e, Counted<W>>>,
+    seekable: Option<Seekable<'e>>,
+    summary: Option<Summary>,
+}
+
+struct Seekable<'e> {
+    zstd: EncoderDict<'e>,
+    limits: FrameLimits,
+    table: SeekTable,
+    frame_start: u64,
+    frame_items: u64,
+}
+
+/// How a single item is written by [`CompressItem::write_item_with`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ItemCodec {
+    /// compress the item, unless that doesn't make it any smaller and stored items are enabled,
+    /// as `write_item` does
+    #[default]
+    Auto,
+    /// always compress the item
+    Compress,
+    /// store the item uncompressed, e.g. because it's already compressed; requires
+    /// [`CompressOptions::with_stored_items`]
+    Store,
 }
 
 /// Concrete implementation of the compressed item writer
 pub struct CompressItem<'d, W> {
     off: u64,
     inner: W,
-    zstd: EncoderDict<'d>,
+    compressor: ItemCompressor<'d>,
+    codec: Codec,
+    buf: Vec<u8>,
+    summary: Option<Summary>,
+    stored_items: bool,
+}
+
+/// Concrete implementation of the uncompressed writer
+pub struct CompressPlain<W> {
+    off: u64,
+    inner: W,
+    summary: Option<Summary>,
 }
 
 impl<'e, W: Write> Compress<W> for CompressStream<'e, W> {
     fn write_item(&mut self, item: &[u8]) -> Result<u64> {
-        let len = u64::try_from(item.len()).map_err(|_| Error::LengthOverflow)?;
-        self.inner.write_all(&len.to_le_bytes())?;
-        self.inner.write_all(item)?;
-        self.off = self.off.checked_add(len).ok_or(Error::LengthOverflow)?;
-        Ok(self.off)
+        let len = self.append(&[item])?;
+        self.item_off = self
+            .item_off
+            .checked_add(len)
+            .ok_or(Error::LengthOverflow)?;
+        Ok(self.item_off)
+    }
+
+    fn write_item_vectored(&mut self, item: &[&[u8]]) -> Result<u64> {
+        let start = self.off;
+        let len = self.append(item)?;
+        self.item_off = self
+            .item_off
+            .checked_add(GLOBAL_MARKER_LEN + len)
+            .ok_or(Error::LengthOverflow)?;
+        Ok(start)
     }
 
     fn finish(mut self) -> Result<W> {
-        self.inner.write_all(&footer())?;
-        let mut w = self.inner.finish()?;
+        let mut inner = self.inner.take().ok_or(Error::ApiMisuse)?;
+        inner.write_all(&footer())?;
+        let mut w = inner.finish()?;
+        if let Some(seekable) = &mut self.seekable {
+            seekable.finish_frame(&w, self.off + GLOBAL_MARKER_LEN);
+        }
+        // outside the compressed stream, so it can be read without decompressing
+        if let Some(summary) = self.summary {
+            w.write_all(&summary.finish().to_frame())?;
+        }
+        if let Some(seekable) = self.seekable {
+            seekable.table.write_to(&mut w)?;
+        }
+        let mut w = w.inner;
         w.flush()?;
         Ok(w)
     }
 
     fn flush(&mut self) -> Result<()> {
-        self.inner.flush()?;
+        self.encoder()?.flush()?;
         Ok(())
     }
 }
 
 impl<'e, W: Write> CompressStream<'e, W> {
-    pub fn write_item_vectored(&mut self, item: &[&[u8]]) -> Result<u64> {
-        let mut len: u64 = 0;
-        for slice in item {
-            len = len
-                .checked_add(u64::try_from(slice.len()).map_err(|_| Error::LengthOverflow)?)
-                .ok_or(Error::LengthOverflow)?;
+    /// # Panics
+    ///
+    /// If a previous write failed while cutting a seekable frame.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self
+            .inner
+            .as_mut()
+            .expect("writer poisoned while cutting a frame")
+            .get_mut()
+            .inner
+    }
+
+    /// write an item, returning its length
+    fn append(&mut self, item: &[&[u8]]) -> Result<u64> {
+        let len = vectored_len(item)?;
+        if let Some(seekable) = &self.seekable {
+            // frames which the seek table can't record are refused before anything is written
+            if GLOBAL_MARKER_LEN + len > MAX_FRAME_BYTES {
+                return Err(Error::LengthOverflow);
+            }
+            if self.off - seekable.frame_start + GLOBAL_MARKER_LEN + len > MAX_FRAME_BYTES {
+                self.cut_frame()?;
+            }
         }
-        self.inner.write_all(&len.to_le_bytes())?;
+        let inner = self.encoder()?;
+        inner.write_all(&len.to_le_bytes())?;
         for slice in item {
-            self.inner.write_all(slice)?;
+            inner.write_all(slice)?;
+        }
+        if let Some(summary) = &mut self.summary {
+            summary.item(len, item, Some(len));
         }
-        let start = self.off;
         self.off = self
             .off
             .checked_add(GLOBAL_MARKER_LEN + len)
             .ok_or(Error::LengthOverflow)?;
-        Ok(start)
+
+        if let Some(seekable) = &mut self.seekable {
+            seekable.frame_items += 1;
+            let frame_bytes = self.off - seekable.frame_start;
+            if seekable.limits.bytes.is_some_and(|max| frame_bytes >= max)
+                || seekable
+                    .limits
+                    .items
+                    .is_some_and(|max| seekable.frame_items >= max)
+            {
+                self.cut_frame()?;
+            }
+        }
+        Ok(len)
     }
 
-    pub fn get_mut(&mut self) -> &mut W {
-        self.inner.get_mut()
+    fn encoder(&mut self) -> Result<&mut Encoder<'e, Counted<W>>> {
+        // a previous error while cutting a frame leaves us without an encoder
+        self.inner.as_mut().ok_or(Error::ApiMisuse)
+    }
+
+    fn cut_frame(&mut self) -> Result<()> {
+        let seekable = self
+            .seekable
+            .as_mut()
+            .ok_or(Error::Internal("cutting frames requires a seek table"))?;
+        let inner = self.inner.take().ok_or(Error::ApiMisuse)?;
+        let w = inner.finish()?;
+        seekable.finish_frame(&w, self.off);
+        self.inner = Some(seekable.zstd.encode(w)?);
+        Ok(())
+    }
+}
+
+impl Seekable<'_> {
+    fn finish_frame<W>(&mut self, w: &Counted<W>, off: u64) {
+        let compressed_start = self.table.compressed_len();
+        self.table
+            .push(w.count - compressed_start, off - self.frame_start);
+        self.frame_start = off;
+        self.frame_items = 0;
     }
 }
 
 impl<'d, W: Write> Compress<W> for CompressItem<'d, W> {
     fn write_item(&mut self, item: &[u8]) -> Result<u64> {
-        let original_len = u64::try_from(item.len()).map_err(|_| Error::LengthOverflow)?;
-        let mut buf = Vec::with_capacity(item.len() / 4 + 30);
-        let mut writer = self.zstd.encode(&mut buf)?;
-        writer.set_pledged_src_size(Some(original_len))?;
-        writer.include_contentsize(true)?;
-        writer.write_all(item)?;
-        writer.finish()?;
-
-        let new_len = u64::try_from(buf.len()).map_err(|_| Error::LengthOverflow)?;
-        self.inner.write_all(&new_len.to_le_bytes())?;
-        self.inner.write_all(&buf)?;
+        self.write_item_vectored(&[item])
+    }
+
+    fn write_item_vectored(&mut self, item: &[&[u8]]) -> Result<u64> {
+        self.write_vectored_with(item, ItemCodec::Auto)
+    }
+
+    fn finish(self) -> Result<W> {
+        let mut w = self.inner;
+        w.write_all(&footer())?;
+        if let Some(summary) = self.summary {
+            w.write_all(&summary.finish().to_bytes())?;
+        }
+        w.flush()?;
+        Ok(w)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Compress<W> for CompressPlain<W> {
+    fn write_item(&mut self, item: &[u8]) -> Result<u64> {
+        self.write_item_vectored(&[item])
+    }
+
+    fn write_item_vectored(&mut self, item: &[&[u8]]) -> Result<u64> {
         let start = self.off;
-        self.off = self
-            .off
-            .checked_add(GLOBAL_MARKER_LEN + new_len)
+        let len = vectored_len(item)?;
+        let end = start
+            .checked_add(GLOBAL_MARKER_LEN + len)
             .ok_or(Error::LengthOverflow)?;
+        self.inner.write_all(&len.to_le_bytes())?;
+        for slice in item {
+            self.inner.write_all(slice)?;
+        }
+        if let Some(summary) = &mut self.summary {
+            summary.item(len, item, Some(len));
+        }
+        self.off = end;
         Ok(start)
     }
 
     fn finish(self) -> Result<W> {
         let mut w = self.inner;
         w.write_all(&footer())?;
+        if let Some(summary) = self.summary {
+            w.write_all(&summary.finish().to_bytes())?;
+        }
         w.flush()?;
         Ok(w)
     }
@@ -117,42 +280,291 @@ impl<'d, W: Write> Compress<W> for CompressItem<'d, W> {
     }
 }
 
+impl<W> CompressPlain<W> {
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+}
+
 impl<'d, W: Write> CompressItem<'d, W> {
+    /// the codec each item is compressed with
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// whether items may be stored uncompressed, see [`CompressOptions::with_stored_items`]
+    pub fn stored_items(&self) -> bool {
+        self.stored_items
+    }
+
+    /// Append an item, choosing whether it's compressed, e.g. to store items which are already
+    /// compressed without trying to compress them again.
+    pub fn write_item_with(&mut self, item: &[u8], codec: ItemCodec) -> Result<u64> {
+        self.write_vectored_with(&[item], codec)
+    }
+
+    fn write_vectored_with(&mut self, item: &[&[u8]], codec: ItemCodec) -> Result<u64> {
+        let original_len = vectored_len(item)?;
+        let stored = stored_header(original_len).filter(|_| self.stored_items);
+        let store = match codec {
+            ItemCodec::Store if !self.stored_items => return Err(Error::ApiMisuse),
+            ItemCodec::Store => true,
+            ItemCodec::Auto | ItemCodec::Compress => {
+                self.compressor
+                    .compress(item, original_len, &mut self.buf)?;
+                // a compressed item which looks stored would be misread, so it has to be stored
+                let misread = !matches!(stored_content(&self.buf), Ok(None));
+                let larger = codec == ItemCodec::Auto
+                    && self.buf.len() as u64 >= STORED_HEADER_LEN + original_len;
+                stored.is_some() && (misread || larger)
+            }
+        };
+        if store {
+            let header = stored.ok_or(Error::LengthOverflow)?;
+            self.buf.clear();
+            self.buf.extend_from_slice(&header);
+            for slice in item {
+                self.buf.extend_from_slice(slice);
+            }
+        }
+
+        let buf = std::mem::take(&mut self.buf);
+        let start = self.append(&buf, Some(original_len));
+        self.buf = buf;
+        start
+    }
+
+    /// Append an item which is already a single zstd frame (or a stored item, if this writer has
+    /// [`stored_items`](Self::stored_items)), such as one
+    /// returned by [`RawExpand::next_raw`](crate::RawExpand::next_raw), without recompressing it.
+    ///
+    /// Readers will need whichever dictionary the frame was compressed with. Items for
+    /// other codecs must be in this writer's codec, and aren't checked.
+    pub fn write_frame(&mut self, frame: &[u8]) -> Result<u64> {
+        let stored = stored_content(frame)?.is_some();
+        if stored && !self.stored_items {
+            return Err(Error::ApiMisuse);
+        }
+        if !stored
+            && self.codec == Codec::Zstd
+            && (!frame.starts_with(&ZSTD_MAGIC)
+                || find_frame_compressed_size(frame) != Ok(frame.len()))
+        {
+            return Err(Error::InvalidItem);
+        }
+        self.append(
+            frame,
+            frame_content_len(self.codec, self.stored_items, frame),
+        )
+    }
+
+    fn append(&mut self, frame: &[u8], content_len: Option<u64>) -> Result<u64> {
+        let new_len = u64::try_from(frame.len()).map_err(|_| Error::LengthOverflow)?;
+        self.inner.write_all(&new_len.to_le_bytes())?;
+        self.inner.write_all(frame)?;
+        if let Some(summary) = &mut self.summary {
+            summary.item(new_len, &[frame], content_len);
+        }
+        let start = self.off;
+        self.off = self
+            .off
+            .checked_add(GLOBAL_MARKER_LEN + new_len)
+            .ok_or(Error::LengthOverflow)?;
+        Ok(start)
+    }
+
     pub fn get_mut(&mut self) -> &mut W {
         &mut self.inner
     }
 }
 
+fn vectored_len(item: &[&[u8]]) -> Result<u64> {
+    let mut len: u64 = 0;
+    for slice in item {
+        len = len
+            .checked_add(u64::try_from(slice.len()).map_err(|_| Error::LengthOverflow)?)
+            .ok_or(Error::LengthOverflow)?;
+    }
+    Ok(len)
+}
+
 impl<'d> CompressOptions<'d> {
     pub fn stream_compress<W: Write>(&self, inner: W) -> Result<CompressStream<'d, W>> {
-        let mut inner = self.zstd.encode(inner)?;
-        inner.write_all(&header(Kinds::Plain))?;
+        // seek tables and trailers are skippable frames, which only zstd readers skip
+        if (self.frames.is_some() || self.trailer) && self.zstd.codec != Codec::Zstd {
+            return Err(Error::ApiMisuse);
+        }
+        let mut inner = self.zstd.encode(Counted { inner, count: 0 })?;
+        let header = Header::new(Kinds::Plain)
+            .with_trailer(self.trailer)
+            .with_metadata(&self.metadata)?
+            .to_bytes()?;
+        inner.write_all(&header)?;
+        let seekable = self.frames.map(|limits| Seekable {
+            zstd: self.zstd.clone(),
+            limits,
+            table: SeekTable::default(),
+            frame_start: 0,
+            frame_items: 0,
+        });
         Ok(CompressStream {
-            off: GLOBAL_MARKER_LEN,
+            off: header.len() as u64,
+            item_off: header.len() as u64,
+            inner: Some(inner),
+            seekable,
+            summary: self.trailer.then(|| Summary::new(&header)),
+        })
+    }
+
+    /// Write items without any compression, e.g. because they're already compressed. Only the
+    /// trailer and metadata options apply.
+    pub fn plain<W: Write>(&self, mut inner: W) -> Result<CompressPlain<W>> {
+        let header = Header::new(Kinds::Plain)
+            .with_trailer(self.trailer)
+            .with_metadata(&self.metadata)?
+            .to_bytes()?;
+        inner.write_all(&header)?;
+        Ok(CompressPlain {
+            off: header.len() as u64,
             inner,
+            summary: self.trailer.then(|| Summary::new(&header)),
         })
     }
 
     pub fn item_compress<W: Write>(&self, mut inner: W) -> Result<CompressItem<'d, W>> {
-        inner.write_all(&header(Kinds::ItemCompressed))?;
+        let header = Header::new(Kinds::items(self.zstd.codec))
+            .with_trailer(self.trailer)
+            .with_stored_items(self.stored_items)
+            .with_metadata(&self.metadata)?
+            .to_bytes()?;
+        inner.write_all(&header)?;
         Ok(CompressItem {
-            off: GLOBAL_MARKER_LEN,
+            off: header.len() as u64,
             inner,
-            zstd: self.zstd.clone(),
+            compressor: self.zstd.compressor()?,
+            codec: self.zstd.codec,
+            buf: Vec::new(),
+            summary: self.trailer.then(|| Summary::new(&header)),
+            stored_items: self.stored_items,
         })
     }
 }
 
 impl<'d> CompressOptions<'d> {
+    /// Compress with a codec other than zstd (the default). The zstd-specific options, such as
+    /// dictionaries, workers and seekable frames, only apply to zstd.
+    #[must_use]
+    pub fn with_codec(mut self, val: Codec) -> Self {
+        self.zstd.codec = val;
+        self
+    }
+
+    /// The zstd compression level, or the xz preset (0 to 9); ignored by
+    /// [`with_dict`](Self::with_dict), whose prepared dictionaries carry their own level.
     #[must_use]
     pub fn with_level(mut self, val: i32) -> Self {
-        self.zstd = EncoderDict::None(val);
+        self.zstd.level = val;
         self
     }
 
     #[must_use]
     pub fn with_dict(mut self, dict: &'d EncoderDictionary<'static>) -> Self {
-        self.zstd = EncoderDict::Dict(dict);
+        self.zstd.dict = Dict::Prepared(dict);
+        self
+    }
+
+    /// Use a raw (unprepared) dictionary, compressing at the [`with_level`](Self::with_level) level
+    #[must_use]
+    pub fn with_dict_bytes(mut self, dict: &'d [u8]) -> Self {
+        self.zstd.dict = Dict::Raw(dict);
+        self
+    }
+
+    #[must_use]
+    pub fn without_dict(mut self) -> Self {
+        self.zstd.dict = Dict::None;
+        self
+    }
+
+    /// Enable zstd's long distance matching; this is only really useful for stream compression
+    #[must_use]
+    pub fn with_long_distance_matching(mut self, val: bool) -> Self {
+        self.zstd.long_distance = val;
+        self
+    }
+
+    /// The zstd window size, as a power of two. Readers need a matching
+    /// [`ExpandOptions::with_window_log_max`](crate::ExpandOptions::with_window_log_max)
+    /// for values above 27.
+    #[must_use]
+    pub fn with_window_log(mut self, val: u32) -> Self {
+        self.zstd.window_log = Some(val);
+        self
+    }
+
+    /// Write a checksum of the content into each zstd frame
+    #[must_use]
+    pub fn with_checksum(mut self, val: bool) -> Self {
+        self.zstd.checksum = val;
+        self
+    }
+
+    /// Compress streams using this many zstd worker threads (default: 0, compress on the calling thread)
+    #[must_use]
+    pub fn with_workers(mut self, val: u32) -> Self {
+        self.zstd.workers = val;
+        self
+    }
+
+    /// Cut a new zstd frame, in a seekable stream, after an item takes the frame past this many
+    /// (uncompressed) bytes. A seek table is written at the end of the archive.
+    #[must_use]
+    pub fn with_frame_bytes(mut self, val: u64) -> Self {
+        self.frames.get_or_insert_with(FrameLimits::default).bytes = Some(val);
+        self
+    }
+
+    /// Cut a new zstd frame, in a seekable stream, after this many items.
+    /// A seek table is written at the end of the archive.
+    #[must_use]
+    pub fn with_frame_items(mut self, val: u64) -> Self {
+        self.frames.get_or_insert_with(FrameLimits::default).items = Some(val);
+        self
+    }
+
+    /// Record the decompressed size of each item-compressed frame, which allows faster reading (default: on)
+    #[must_use]
+    pub fn with_content_size(mut self, val: bool) -> Self {
+        self.zstd.content_size = val;
+        self
+    }
+
+    /// Record a key and value in the archive's header, e.g. who wrote it, or the schema of the items.
+    /// Readers see the entries in the order they were added, through `Expand::archive_metadata`.
+    #[must_use]
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.push((key.into(), value.into()));
+        self
+    }
+
+    /// Write a trailer after the footer, with the item count, sizes and a checksum of the archive,
+    /// which can be read with [`ExpandOptions::trailer`](crate::ExpandOptions::trailer) without
+    /// reading the items, and is checked by `RawExpand::check_eof`. Stream-compressed archives
+    /// must be zstd, and have it after the compressed stream, where `check_eof` can't read it.
+    /// (default: off)
+    #[must_use]
+    pub fn with_trailer(mut self, val: bool) -> Self {
+        self.trailer = val;
+        self
+    }
+
+    /// Allow item-compressed archives to store items uncompressed, when compression doesn't make
+    /// them smaller, or they're written with [`ItemCodec::Store`]. The header is marked so that
+    /// readers which don't understand stored items refuse the archive, instead of misreading
+    /// them as empty. (default: off)
+    #[must_use]
+    pub fn with_stored_items(mut self, val: bool) -> Self {
+        self.stored_items = val;
         self
     }
 }
@@ -1,42 +1,430 @@
-use std::io::{BufRead, Write};
+use std::io;
+use std::io::{BufRead, Read, Write};
 
+use zstd::bulk::{Compressor, Decompressor};
 use zstd::dict::{DecoderDictionary, EncoderDictionary};
+use zstd::zstd_safe::zstd_sys::ZSTD_EndDirective;
+use zstd::zstd_safe::{compress_bound, CParameter, DParameter, InBuffer, OutBuffer};
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::header::stored_content;
+
+/// The compression used for a stream, or for each item
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Codec {
+    #[default]
+    Zstd,
+    /// lz4, for faster decompression; streams are lz4 frames, items are lz4 blocks
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// xz (lzma2), for smaller cold storage
+    #[cfg(feature = "xz")]
+    Xz,
+}
+
+impl Codec {
+    /// a short, lowercase name for the codec, e.g. `zstd`
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Zstd => "zstd",
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => "lz4",
+            #[cfg(feature = "xz")]
+            Codec::Xz => "xz",
+        }
+    }
+}
+
+/// A layer of stream compression around an archive, which is removed before the header is read
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Wrapper {
+    /// a codec which archives can also be written with
+    Codec(Codec),
+    /// gzip, which is only read
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// bzip2, which is only read
+    #[cfg(feature = "bzip2")]
+    Bzip2,
+}
+
+impl Wrapper {
+    /// the name of the codec or wrapper, e.g. `gzip`
+    pub fn name(self) -> &'static str {
+        match self {
+            Wrapper::Codec(codec) => codec.name(),
+            #[cfg(feature = "gzip")]
+            Wrapper::Gzip => "gzip",
+            #[cfg(feature = "bzip2")]
+            Wrapper::Bzip2 => "bzip2",
+        }
+    }
+}
 
 #[derive(Clone)]
-pub enum EncoderDict<'d> {
-    None(i32),
-    Dict(&'d EncoderDictionary<'static>),
+pub struct EncoderDict<'d> {
+    pub codec: Codec,
+    pub level: i32,
+    pub dict: Dict<'d>,
+    pub long_distance: bool,
+    pub window_log: Option<u32>,
+    pub checksum: bool,
+    pub content_size: bool,
+    pub workers: u32,
 }
 
 #[derive(Clone, Default)]
-pub enum DecoderDict<'d> {
+pub enum Dict<'d> {
     #[default]
     None,
-    Dict(&'d DecoderDictionary<'static>),
+    Prepared(&'d EncoderDictionary<'static>),
+    Raw(&'d [u8]),
+}
+
+#[derive(Clone, Default)]
+pub struct DecoderDict<'d> {
+    pub dict: Option<&'d DecoderDictionary<'static>>,
+    pub window_log_max: Option<u32>,
+}
+
+/// A stream compressor, for whichever codec was chosen
+pub enum Encoder<'e, W: Write> {
+    Zstd(zstd::Encoder<'e, W>),
+    #[cfg(feature = "lz4")]
+    // boxed, as they're much larger than a zstd encoder
+    Lz4(Box<lz4_flex::frame::FrameEncoder<W>>),
+    #[cfg(feature = "xz")]
+    Xz(Box<xz2::write::XzEncoder<W>>),
+}
+
+/// An item compressor, for whichever codec was chosen
+pub enum ItemCompressor<'d> {
+    Zstd(Compressor<'d>),
+    #[cfg(feature = "lz4")]
+    Lz4,
+    #[cfg(feature = "xz")]
+    Xz(u32),
 }
 
 impl<'d> EncoderDict<'d> {
-    pub fn encode<'e: 'd, W: Write>(&self, inner: W) -> Result<zstd::Encoder<'e, W>> {
+    pub fn encode<'e: 'd, W: Write>(&self, inner: W) -> Result<Encoder<'e, W>> {
+        match self.codec {
+            Codec::Zstd => Ok(Encoder::Zstd(self.zstd_encoder(inner)?)),
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => Ok(Encoder::Lz4(Box::new(lz4_flex::frame::FrameEncoder::new(
+                inner,
+            )))),
+            #[cfg(feature = "xz")]
+            Codec::Xz => Ok(Encoder::Xz(Box::new(xz2::write::XzEncoder::new(
+                inner,
+                self.xz_level(),
+            )))),
+        }
+    }
+
+    fn zstd_encoder<'e: 'd, W: Write>(&self, inner: W) -> Result<zstd::Encoder<'e, W>> {
+        let mut encoder = match self.dict {
+            Dict::None => zstd::Encoder::new(inner, self.level)?,
+            Dict::Prepared(p) => zstd::Encoder::with_prepared_dictionary(inner, p)?,
+            Dict::Raw(d) => zstd::Encoder::with_dictionary(inner, self.level, d)?,
+        };
+        for param in self.parameters() {
+            encoder.set_parameter(param)?;
+        }
+        // only streams can be split into jobs; the item compressor leaves this at zero
+        if self.workers > 0 {
+            encoder.multithread(self.workers)?;
+        }
+        Ok(encoder)
+    }
+
+    pub fn compressor(&self) -> Result<ItemCompressor<'d>> {
+        match self.codec {
+            Codec::Zstd => Ok(ItemCompressor::Zstd(self.zstd_compressor()?)),
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => Ok(ItemCompressor::Lz4),
+            #[cfg(feature = "xz")]
+            Codec::Xz => Ok(ItemCompressor::Xz(self.xz_level())),
+        }
+    }
+
+    fn zstd_compressor(&self) -> Result<Compressor<'d>> {
+        let mut compressor = match self.dict {
+            Dict::None => Compressor::new(self.level)?,
+            Dict::Prepared(p) => Compressor::with_prepared_dictionary(p)?,
+            Dict::Raw(d) => Compressor::with_dictionary(self.level, d)?,
+        };
+        for param in self.parameters() {
+            compressor.set_parameter(param)?;
+        }
+        Ok(compressor)
+    }
+
+    fn parameters(&self) -> Vec<CParameter> {
+        let mut params = vec![
+            CParameter::EnableLongDistanceMatching(self.long_distance),
+            CParameter::ChecksumFlag(self.checksum),
+            CParameter::ContentSizeFlag(self.content_size),
+        ];
+        if let Some(window_log) = self.window_log {
+            params.push(CParameter::WindowLog(window_log));
+        }
+        params
+    }
+
+    // xz's presets run from 0 to 9; zstd's default level (0) means xz's default, 6
+    #[cfg(feature = "xz")]
+    fn xz_level(&self) -> u32 {
+        match self.level {
+            0 => 6,
+            level => level.clamp(0, 9) as u32,
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Zstd(e) => e.write(buf),
+            #[cfg(feature = "lz4")]
+            Encoder::Lz4(e) => e.write(buf),
+            #[cfg(feature = "xz")]
+            Encoder::Xz(e) => e.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Zstd(e) => e.flush(),
+            #[cfg(feature = "lz4")]
+            Encoder::Lz4(e) => e.flush(),
+            #[cfg(feature = "xz")]
+            Encoder::Xz(e) => e.flush(),
+        }
+    }
+}
+
+impl<W: Write> Encoder<'_, W> {
+    pub fn finish(self) -> Result<W> {
         Ok(match self {
-            EncoderDict::None(level) => zstd::Encoder::new(inner, *level)?,
-            EncoderDict::Dict(p) => zstd::Encoder::with_prepared_dictionary(inner, p)?,
+            Encoder::Zstd(e) => e.finish()?,
+            #[cfg(feature = "lz4")]
+            Encoder::Lz4(e) => e.finish().map_err(io::Error::from)?,
+            #[cfg(feature = "xz")]
+            Encoder::Xz(e) => e.finish()?,
         })
     }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        match self {
+            Encoder::Zstd(e) => e.get_mut(),
+            #[cfg(feature = "lz4")]
+            Encoder::Lz4(e) => e.get_mut(),
+            #[cfg(feature = "xz")]
+            Encoder::Xz(e) => e.get_mut(),
+        }
+    }
+}
+
+impl ItemCompressor<'_> {
+    /// compress the concatenation of the slices, `original_len` bytes, into `buf`
+    pub fn compress(&mut self, item: &[&[u8]], original_len: u64, buf: &mut Vec<u8>) -> Result<()> {
+        let original_len = usize::try_from(original_len).map_err(|_| Error::LengthOverflow)?;
+        buf.clear();
+        match self {
+            ItemCompressor::Zstd(compressor) => compress_zstd(compressor, item, original_len, buf),
+            #[cfg(feature = "lz4")]
+            ItemCompressor::Lz4 => {
+                let joined;
+                let input = match item {
+                    [single] => *single,
+                    _ => {
+                        joined = item.concat();
+                        &joined
+                    }
+                };
+                // blocks don't record their length, so it's prefixed, as lz4_flex does
+                let prefix = u32::try_from(original_len).map_err(|_| Error::LengthOverflow)?;
+                buf.extend_from_slice(&prefix.to_le_bytes());
+                buf.resize(
+                    4 + lz4_flex::block::get_maximum_output_size(original_len),
+                    0,
+                );
+                let written = lz4_flex::block::compress_into(input, &mut buf[4..])
+                    .map_err(io::Error::other)?;
+                buf.truncate(4 + written);
+                Ok(())
+            }
+            #[cfg(feature = "xz")]
+            ItemCompressor::Xz(level) => {
+                let mut encoder = xz2::write::XzEncoder::new(buf, *level);
+                for slice in item {
+                    encoder.write_all(slice)?;
+                }
+                encoder.finish()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+// this uses the streaming api on the reused context, so the slices needn't be contiguous
+fn compress_zstd(
+    compressor: &mut Compressor,
+    item: &[&[u8]],
+    original_len: usize,
+    buf: &mut Vec<u8>,
+) -> Result<()> {
+    let cctx = compressor.context_mut();
+    cctx.set_pledged_src_size(Some(original_len as u64))
+        .map_err(zstd_error)?;
+
+    buf.reserve(compress_bound(original_len));
+    let mut pos = 0;
+    for slice in item {
+        let mut input = InBuffer::around(slice);
+        while input.pos() < slice.len() {
+            buf.reserve(slice.len() - input.pos());
+            let mut output = OutBuffer::around_pos(buf, pos);
+            cctx.compress_stream2(&mut output, &mut input, ZSTD_EndDirective::ZSTD_e_continue)
+                .map_err(zstd_error)?;
+            pos = output.pos();
+        }
+    }
+
+    loop {
+        let mut output = OutBuffer::around_pos(buf, pos);
+        let remaining = cctx
+            .compress_stream2(
+                &mut output,
+                &mut InBuffer::around(&[]),
+                ZSTD_EndDirective::ZSTD_e_end,
+            )
+            .map_err(zstd_error)?;
+        pos = output.pos();
+        if remaining == 0 {
+            return Ok(());
+        }
+        buf.reserve(remaining);
+    }
 }
 
 impl<'d> DecoderDict<'d> {
     pub fn decode<R: BufRead>(&self, inner: R) -> Result<zstd::Decoder<'d, R>> {
-        Ok(match self {
-            DecoderDict::None => zstd::Decoder::with_buffer(inner)?,
-            DecoderDict::Dict(p) => zstd::Decoder::with_prepared_dictionary(inner, p)?,
+        let mut decoder = match self.dict {
+            None => zstd::Decoder::with_buffer(inner)?,
+            Some(p) => zstd::Decoder::with_prepared_dictionary(inner, p)?,
+        };
+        if let Some(window_log_max) = self.window_log_max {
+            decoder.window_log_max(window_log_max)?;
+        }
+        Ok(decoder)
+    }
+
+    pub fn decompressor(&self) -> Result<Decompressor<'d>> {
+        let mut decompressor = match self.dict {
+            None => Decompressor::new()?,
+            Some(p) => Decompressor::with_prepared_dictionary(p)?,
+        };
+        if let Some(window_log_max) = self.window_log_max {
+            decompressor.set_parameter(DParameter::WindowLogMax(window_log_max))?;
+        }
+        Ok(decompressor)
+    }
+
+    /// decompress a stream, of any codec or wrapper
+    pub fn decode_stream<R: BufRead + 'd>(
+        &self,
+        wrapper: Wrapper,
+        inner: R,
+    ) -> Result<Box<dyn Read + 'd>> {
+        Ok(match wrapper {
+            Wrapper::Codec(Codec::Zstd) => Box::new(self.decode(inner)?),
+            #[cfg(feature = "lz4")]
+            Wrapper::Codec(Codec::Lz4) => Box::new(lz4_flex::frame::FrameDecoder::new(inner)),
+            #[cfg(feature = "xz")]
+            Wrapper::Codec(Codec::Xz) => {
+                Box::new(xz2::bufread::XzDecoder::new_multi_decoder(inner))
+            }
+            #[cfg(feature = "gzip")]
+            Wrapper::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(inner)),
+            #[cfg(feature = "bzip2")]
+            Wrapper::Bzip2 => Box::new(bzip2::bufread::MultiBzDecoder::new(inner)),
         })
     }
+
+    /// decompress an item, of any codec, or copy a stored item, if the archive has
+    /// `stored_items`, into `out`, failing if it's larger than `max_item_size`
+    pub fn decode_item(
+        &self,
+        codec: Codec,
+        stored_items: bool,
+        item: &[u8],
+        max_item_size: u64,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        out.clear();
+        if let Some(content) = stored_content(item)?.filter(|_| stored_items) {
+            if content.len() as u64 > max_item_size {
+                return Err(Error::InvalidItem);
+            }
+            out.extend_from_slice(content);
+            return Ok(());
+        }
+        match codec {
+            Codec::Zstd => {
+                let mut decoder = self.decode(item)?.take(max_item_size.saturating_add(1));
+                decoder.read_to_end(out)?;
+            }
+            #[cfg(feature = "lz4")]
+            Codec::Lz4 => {
+                let (prefix, block) = item.split_first_chunk::<4>().ok_or(Error::InvalidItem)?;
+                let len = u64::from(u32::from_le_bytes(*prefix));
+                // lz4 can't expand a block more than 255 times, so don't allocate for more
+                if len > max_item_size || len > (block.len() as u64).saturating_mul(255) {
+                    return Err(Error::InvalidItem);
+                }
+                out.try_reserve_exact(usize::try_from(len).map_err(|_| Error::LengthOverflow)?)?;
+                out.resize(len as usize, 0);
+                let written = lz4_flex::block::decompress_into(block, out)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                if written as u64 != len {
+                    return Err(Error::ContentSizeMismatch {
+                        declared: len,
+                        actual: written as u64,
+                    });
+                }
+            }
+            #[cfg(feature = "xz")]
+            Codec::Xz => {
+                let mut decoder =
+                    xz2::read::XzDecoder::new(item).take(max_item_size.saturating_add(1));
+                decoder.read_to_end(out)?;
+            }
+        }
+        if out.len() as u64 > max_item_size {
+            return Err(Error::InvalidItem);
+        }
+        Ok(())
+    }
+}
+
+pub fn zstd_error(code: zstd::zstd_safe::ErrorCode) -> io::Error {
+    io::Error::other(zstd::zstd_safe::get_error_name(code))
 }
 
 impl Default for EncoderDict<'_> {
     fn default() -> Self {
-        EncoderDict::None(0)
+        EncoderDict {
+            codec: Codec::Zstd,
+            level: 0,
+            dict: Dict::None,
+            long_distance: false,
+            window_log: None,
+            checksum: false,
+            content_size: true,
+            workers: 0,
+        }
     }
 }
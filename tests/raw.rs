@@ -0,0 +1,302 @@
+use std::io;
+use std::io::Read;
+
+use archiv::{Codec, Compress, CompressOptions, Error, ExpandOptions, ItemCodec, Layout};
+
+fn raw_items(file: &[u8], layout: Layout) -> anyhow::Result<Vec<(u64, Vec<u8>)>> {
+    let opts = ExpandOptions::default();
+    let mut archiv = opts.raw(io::Cursor::new(file))?;
+    assert_eq!(layout, archiv.layout());
+    let mut frames = opts.frame_expand(Codec::Zstd, archiv.stored_items())?;
+    let mut raw = Vec::new();
+    let mut items = Vec::new();
+    while let Some(item) = archiv.next_raw(&mut raw)? {
+        assert_eq!(item.index, items.len() as u64);
+        assert_eq!(item.stored_len, raw.len() as u64);
+        let mut out = Vec::new();
+        archiv.expand_raw(&raw, &mut out)?;
+        assert_eq!(item.content_len, Some(out.len() as u64));
+        if layout == Layout::ItemCompressed {
+            let mut bulk = Vec::new();
+            frames.expand(&raw, &mut bulk)?;
+            assert_eq!(out, bulk);
+        }
+        items.push((item.offset, out));
+    }
+    archiv.check_eof()?;
+    Ok(items)
+}
+
+#[test]
+fn raw_offsets() -> anyhow::Result<()> {
+    let originals = [&b"hello world"[..], b"", b"bruises"];
+    let mut stream = CompressOptions::default().stream_compress(Vec::new())?;
+    let mut item = CompressOptions::default().item_compress(Vec::new())?;
+    let mut stream_offsets = Vec::new();
+    let mut item_offsets = Vec::new();
+    for original in originals {
+        stream_offsets.push(stream.write_item_vectored(&[original])?);
+        item_offsets.push(item.write_item(original)?);
+    }
+
+    for (layout, file, offsets) in [
+        (Layout::StreamCompressed, stream.finish()?, stream_offsets),
+        (Layout::ItemCompressed, item.finish()?, item_offsets),
+    ] {
+        let items = raw_items(&file, layout)?;
+        assert_eq!(
+            offsets
+                .into_iter()
+                .zip(originals.map(|v| v.to_vec()))
+                .collect::<Vec<_>>(),
+            items
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn raw_trailing() -> anyhow::Result<()> {
+    let mut file = CompressOptions::default()
+        .item_compress(Vec::new())?
+        .finish()?;
+    file.push(0);
+    let mut archiv = ExpandOptions::default().raw(io::Cursor::new(file))?;
+    assert!(archiv.next_raw(&mut Vec::new())?.is_none());
+    assert!(matches!(archiv.check_eof(), Err(Error::TrailingData)));
+    Ok(())
+}
+
+#[test]
+fn item_at_offsets() -> anyhow::Result<()> {
+    let originals = (0..20)
+        .map(|i| format!("item number {i}"))
+        .collect::<Vec<_>>();
+    let mut item = CompressOptions::default().item_compress(Vec::new())?;
+    let mut stream = CompressOptions::default().stream_compress(Vec::new())?;
+    let mut item_offsets = Vec::new();
+    let mut stream_offsets = Vec::new();
+    for original in &originals {
+        item_offsets.push(item.write_item(original.as_bytes())?);
+        stream_offsets.push(stream.write_item_vectored(&[original.as_bytes()])?);
+    }
+    let stream = stream.finish()?;
+    let plain = zstd::decode_all(io::Cursor::new(&stream))?;
+
+    for (file, offsets) in [(item.finish()?, item_offsets), (plain, stream_offsets)] {
+        let opts = ExpandOptions::default();
+        for (original, &offset) in originals.iter().zip(&offsets) {
+            let mut item = opts.item_at(io::Cursor::new(&file), offset)?;
+            let mut buf = String::new();
+            item.read_to_string(&mut buf)?;
+            assert_eq!(original, &buf);
+        }
+        for offset in [
+            0,
+            3,
+            offsets[4] + 1,
+            file.len() as u64 - 8,
+            file.len() as u64 - 4,
+            file.len() as u64,
+            file.len() as u64 + 100,
+        ] {
+            assert!(
+                matches!(
+                    opts.item_at(io::Cursor::new(&file), offset),
+                    Err(Error::NotItemBoundary { .. })
+                ),
+                "{offset}"
+            );
+        }
+
+        let mut archiv = opts.raw_seekable(io::Cursor::new(&file))?;
+        for _ in 0..7 {
+            assert!(archiv.skip_raw()?);
+        }
+        assert_eq!((7, offsets[7]), (archiv.index(), archiv.offset()));
+        while archiv.skip_raw()? {}
+        assert_eq!(20, archiv.index());
+        archiv.check_eof()?;
+    }
+
+    assert!(matches!(
+        ExpandOptions::default().item_at(io::Cursor::new(&stream), 8),
+        Err(Error::NotSeekable)
+    ));
+    Ok(())
+}
+
+#[test]
+fn write_frame_passthrough() -> anyhow::Result<()> {
+    let originals = [&b"hello world"[..], b"", b"bruises"];
+    let mut source = CompressOptions::default()
+        .with_level(19)
+        .item_compress(Vec::new())?;
+    for original in originals {
+        source.write_item(original)?;
+    }
+    let source = source.finish()?;
+
+    let mut archiv = ExpandOptions::default().raw(io::Cursor::new(&source))?;
+    let mut copy = CompressOptions::default().item_compress(Vec::new())?;
+    let mut raw = Vec::new();
+    while let Some(item) = archiv.next_raw(&mut raw)? {
+        assert_eq!(item.offset, copy.write_frame(&raw)?);
+    }
+    assert!(matches!(
+        copy.write_frame(b"hello"),
+        Err(Error::InvalidItem)
+    ));
+    assert_eq!(source, copy.finish()?);
+    Ok(())
+}
+
+// xorshift, which zstd can't do anything with
+fn incompressible(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+#[test]
+fn stored_items() -> anyhow::Result<()> {
+    let noise = incompressible(4096);
+    let large = incompressible(3 * 1024 * 1024);
+    let text = b"hello world ".repeat(100);
+
+    let mut archiv = CompressOptions::default()
+        .with_stored_items(true)
+        .item_compress(Vec::new())?;
+    archiv.write_item(&noise)?;
+    archiv.write_item(&large)?;
+    archiv.write_item(&text)?;
+    archiv.write_item_with(&text, ItemCodec::Store)?;
+    archiv.write_item_with(b"hi", ItemCodec::Compress)?;
+    let file = archiv.finish()?;
+
+    let mut raw = ExpandOptions::default().raw(io::Cursor::new(&file))?;
+    let mut stored = Vec::new();
+    let mut buf = Vec::new();
+    while let Some(item) = raw.next_raw(&mut buf)? {
+        stored.push(item.stored_len);
+    }
+    // the noise is stored, and the text compressed, unless told otherwise
+    assert_eq!(4096 + 8, stored[0]);
+    assert_eq!(3 * 1024 * 1024 + 8, stored[1]);
+    assert!(stored[2] < text.len() as u64);
+    assert_eq!(text.len() as u64 + 8, stored[3]);
+    assert!(stored[4] > 2 + 8);
+
+    let originals = [noise, large, text.clone(), text, b"hi".to_vec()];
+    let items = raw_items(&file, Layout::ItemCompressed)?;
+    assert_eq!(
+        originals.to_vec(),
+        items.into_iter().map(|(_, item)| item).collect::<Vec<_>>()
+    );
+
+    let mut archiv = ExpandOptions::default().stream(io::Cursor::new(&file))?;
+    for original in &originals {
+        let mut item = Vec::new();
+        archiv
+            .next_item()?
+            .expect("an item")
+            .read_to_end(&mut item)?;
+        assert_eq!(original, &item);
+    }
+    assert!(archiv.next_item()?.is_none());
+    Ok(())
+}
+
+#[test]
+fn frame_expand_oversized_declaration() -> anyhow::Result<()> {
+    // a zstd frame which declares 1GiB of content, but holds a single five byte raw block
+    let mut frame = vec![0x28, 0xb5, 0x2f, 0xfd, 0x80, 0x00];
+    frame.extend_from_slice(&(1u32 << 30).to_le_bytes());
+    frame.extend_from_slice(&[(5 << 3) | 1, 0, 0]);
+    frame.extend_from_slice(b"hello");
+
+    let mut frames = ExpandOptions::default().frame_expand(Codec::Zstd, false)?;
+    let mut out = Vec::new();
+    assert!(frames.expand(&frame, &mut out).is_err());
+    assert!(out.capacity() < 1024 * 1024);
+    Ok(())
+}
+
+#[test]
+fn stored_items_flagged() -> anyhow::Result<()> {
+    let noise = incompressible(4096);
+
+    // without stored items, the header is the original version, which older readers understand
+    let mut archiv = CompressOptions::default().item_compress(Vec::new())?;
+    archiv.write_item(&noise)?;
+    assert!(matches!(
+        archiv.write_item_with(b"hi", ItemCodec::Store),
+        Err(Error::ApiMisuse)
+    ));
+    let file = archiv.finish()?;
+    assert_eq!(0, file[6]);
+    let mut raw = ExpandOptions::default().raw(io::Cursor::new(&file))?;
+    assert!(!raw.stored_items());
+    let mut buf = Vec::new();
+    raw.next_raw(&mut buf)?.expect("an item");
+    assert!(!buf.starts_with(b"\x5e\x2a\x4d\x18"));
+
+    // the stored frame can't be passed through to a writer without stored items
+    let mut stored = CompressOptions::default()
+        .with_stored_items(true)
+        .item_compress(Vec::new())?;
+    stored.write_item(&noise)?;
+    let stored = stored.finish()?;
+    let mut raw = ExpandOptions::default().raw(io::Cursor::new(&stored))?;
+    assert!(raw.stored_items());
+    raw.next_raw(&mut buf)?.expect("an item");
+    assert!(buf.starts_with(b"\x5e\x2a\x4d\x18"));
+    let mut archiv = CompressOptions::default().item_compress(Vec::new())?;
+    assert!(matches!(archiv.write_frame(&buf), Err(Error::ApiMisuse)));
+
+    // the version is bumped, and the flag is one older readers will refuse
+    assert_eq!(1, stored[6]);
+    let flags = u32::from_le_bytes(stored[8..12].try_into()?);
+    assert_ne!(0, flags);
+    assert_eq!(raw_items(&stored, Layout::ItemCompressed)?[0].1, noise);
+    Ok(())
+}
+
+#[test]
+fn skippable_frames_without_flag() -> anyhow::Result<()> {
+    // skippable frames, which look stored, in an archive without stored items, written by hand
+    // as the writer won't
+    let large = incompressible(2 * 1024 * 1024);
+    let mut file = b"\x29\xb6arc\0\0\x01".to_vec();
+    for content in [&b"hello"[..], &large] {
+        file.extend_from_slice(&(content.len() as u64 + 8).to_le_bytes());
+        file.extend_from_slice(b"\x5e\x2a\x4d\x18");
+        file.extend_from_slice(&u32::try_from(content.len())?.to_le_bytes());
+        file.extend_from_slice(content);
+    }
+    file.extend_from_slice(&0xffff_ffff_ffff_fff0u64.to_le_bytes());
+
+    // they're read as zstd frames, which are empty, as older readers did
+    let items = raw_items(&file, Layout::ItemCompressed)?;
+    assert_eq!(
+        vec![Vec::<u8>::new(); 2],
+        items.into_iter().map(|(_, item)| item).collect::<Vec<_>>()
+    );
+    let mut archiv = ExpandOptions::default().stream(io::Cursor::new(&file))?;
+    for _ in 0..2 {
+        let mut item = Vec::new();
+        archiv
+            .next_item()?
+            .expect("an item")
+            .read_to_end(&mut item)?;
+        assert!(item.is_empty());
+    }
+    assert!(archiv.next_item()?.is_none());
+    Ok(())
+}
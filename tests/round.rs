@@ -1,7 +1,7 @@
 use std::io;
 use std::io::Read;
 
-use archiv::{Compress, CompressOptions, Error, ExpandOptions};
+use archiv::{Compress, CompressOptions, DecoderDictionary, Error, ExpandOptions};
 
 fn test_round_trip<W: AsRef<[u8]> + 'static>(
     mut archiv: impl Compress<W>,
@@ -77,3 +77,318 @@ fn compress_opts_lifetime() -> anyhow::Result<()> {
     archiv.finish()?;
     Ok(())
 }
+
+fn vectored_matches<W: AsRef<[u8]>>(
+    mut contiguous: impl Compress<W>,
+    mut vectored: impl Compress<W>,
+) -> anyhow::Result<()> {
+    contiguous.write_item(b"hello world")?;
+    vectored.write_item_vectored(&[b"hello", b" ", b"world"])?;
+    contiguous.write_item(b"")?;
+    vectored.write_item_vectored(&[])?;
+    contiguous.write_item(b"bruises")?;
+    vectored.write_item_vectored(&[b"", b"bruises", b""])?;
+    assert_eq!(contiguous.finish()?.as_ref(), vectored.finish()?.as_ref());
+    Ok(())
+}
+
+#[test]
+fn stream_offsets() -> anyhow::Result<()> {
+    let mut archiv = CompressOptions::default().stream_compress(Vec::new())?;
+    // write_item returns the length so far, without the items' length markers
+    assert_eq!(8 + 11, archiv.write_item(b"hello world")?);
+    assert_eq!(8 + 11 + 7, archiv.write_item(b"bruises")?);
+    // write_item_vectored returns where the item starts
+    assert_eq!(8 + 8 + 11 + 8 + 7, archiv.write_item_vectored(&[b"hi"])?);
+    Ok(())
+}
+
+#[test]
+fn vectored_stream() -> anyhow::Result<()> {
+    let opts = CompressOptions::default();
+    vectored_matches(
+        opts.stream_compress(Vec::new())?,
+        opts.stream_compress(Vec::new())?,
+    )
+}
+
+#[test]
+fn vectored_items() -> anyhow::Result<()> {
+    let opts = CompressOptions::default();
+    vectored_matches(
+        opts.item_compress(Vec::new())?,
+        opts.item_compress(Vec::new())?,
+    )
+}
+
+#[test]
+fn items_reuse_contexts() -> anyhow::Result<()> {
+    let large = "abcdefghijklmnopqrstuvwxyz".repeat(100_000);
+    let mut originals = Vec::new();
+    for i in 0..1000 {
+        originals.push(format!("item {i}"));
+        if i % 300 == 0 {
+            originals.push(large.clone());
+        }
+    }
+    let originals = originals.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    test_round_trip(
+        CompressOptions::default().item_compress(Vec::new())?,
+        &originals,
+    )
+}
+
+#[test]
+fn tuned_options() -> anyhow::Result<()> {
+    let originals = ["hello world", "bruises", ""];
+    let tuned = || {
+        CompressOptions::default()
+            .with_level(19)
+            .with_long_distance_matching(true)
+            .with_window_log(28)
+            .with_checksum(true)
+    };
+    let read = ExpandOptions::default().with_window_log_max(28);
+    for out in [
+        write_all(tuned().stream_compress(Vec::new())?, &originals)?,
+        write_all(tuned().item_compress(Vec::new())?, &originals)?,
+        write_all(
+            tuned().with_content_size(false).item_compress(Vec::new())?,
+            &originals,
+        )?,
+    ] {
+        assert_eq!(originals.as_slice(), read_all(&read, out)?);
+    }
+    Ok(())
+}
+
+#[test]
+fn dict_with_level() -> anyhow::Result<()> {
+    let dict = b"hello world, these are some bruises".repeat(10);
+    let originals = ["hello world", "bruises"];
+    let decoder = DecoderDictionary::copy(&dict);
+    let read = ExpandOptions::default().with_dict(&decoder);
+    for level in [1, 19] {
+        let opts = CompressOptions::default()
+            .with_dict_bytes(&dict)
+            .with_level(level);
+        let out = write_all(opts.item_compress(Vec::new())?, &originals)?;
+        assert_eq!(originals.as_slice(), read_all(&read, out)?);
+    }
+    Ok(())
+}
+
+fn write_all(mut archiv: impl Compress<Vec<u8>>, originals: &[&str]) -> anyhow::Result<Vec<u8>> {
+    for item in originals {
+        archiv.write_item(item.as_bytes())?;
+    }
+    Ok(archiv.finish()?)
+}
+
+fn read_all(opts: &ExpandOptions, file: Vec<u8>) -> anyhow::Result<Vec<String>> {
+    let mut archiv = opts.stream(io::Cursor::new(file))?;
+    let mut items = Vec::new();
+    while let Some(mut v) = archiv.next_item()? {
+        let mut buf = String::new();
+        v.read_to_string(&mut buf)?;
+        items.push(buf);
+    }
+    Ok(items)
+}
+
+#[test]
+fn multithreaded_stream() -> anyhow::Result<()> {
+    let originals = (0..20_000)
+        .map(|i| format!("item {i} {}", "abcdefghij".repeat(i % 50)))
+        .collect::<Vec<_>>();
+    let originals = originals.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+
+    let multi = write_all(
+        CompressOptions::default()
+            .with_workers(4)
+            .stream_compress(Vec::new())?,
+        &originals,
+    )?;
+    assert_eq!(originals, read_all(&ExpandOptions::default(), multi)?);
+    Ok(())
+}
+
+#[cfg(any(feature = "lz4", feature = "xz"))]
+fn codec_round_trip(codec: archiv::Codec) -> anyhow::Result<()> {
+    let opts = CompressOptions::default().with_codec(codec);
+    let items = ["hello world", "bruises", ""];
+    test_round_trip(opts.stream_compress(Vec::new())?, &items)?;
+    test_round_trip(opts.item_compress(Vec::new())?, &items)?;
+
+    let mut raw = opts.item_compress(Vec::new())?;
+    raw.write_item(b"hello world")?;
+    let file = raw.finish()?;
+    let mut raw = ExpandOptions::default().raw(io::Cursor::new(file))?;
+    assert_eq!(Some(codec), raw.codec());
+    // stored items are opt-in, whatever the codec
+    assert!(!raw.stored_items());
+    let mut buf = Vec::new();
+    let item = raw.next_raw(&mut buf)?.expect("an item");
+    let mut expanded = Vec::new();
+    raw.expand_raw(&buf, &mut expanded)?;
+    assert_eq!(b"hello world", expanded.as_slice());
+    assert_eq!(item.stored_len, buf.len() as u64);
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "lz4")]
+fn round_trip_lz4() -> anyhow::Result<()> {
+    codec_round_trip(archiv::Codec::Lz4)
+}
+
+#[test]
+#[cfg(feature = "xz")]
+fn round_trip_xz() -> anyhow::Result<()> {
+    codec_round_trip(archiv::Codec::Xz)
+}
+
+#[test]
+#[cfg(feature = "xz")]
+fn xz_refuses_skippable_frames() {
+    let opts = CompressOptions::default()
+        .with_codec(archiv::Codec::Xz)
+        .with_frame_items(10);
+    assert!(matches!(
+        opts.stream_compress(Vec::new()),
+        Err(Error::ApiMisuse)
+    ));
+    let opts = CompressOptions::default()
+        .with_codec(archiv::Codec::Xz)
+        .with_trailer(true);
+    assert!(matches!(
+        opts.stream_compress(Vec::new()),
+        Err(Error::ApiMisuse)
+    ));
+    assert!(opts.item_compress(Vec::new()).is_ok());
+}
+
+#[cfg(any(feature = "gzip", feature = "bzip2"))]
+fn wrapped_round_trip(wrap: impl Fn(&[u8]) -> io::Result<Vec<u8>>) -> anyhow::Result<()> {
+    let items = ["hello world", "bruises"];
+    let stream = write_all(
+        CompressOptions::default().stream_compress(Vec::new())?,
+        &items,
+    )?;
+    let item = write_all(
+        CompressOptions::default().item_compress(Vec::new())?,
+        &items,
+    )?;
+    let opts = ExpandOptions::default();
+    for (file, layout) in [
+        (stream, archiv::Layout::StreamCompressed),
+        (item, archiv::Layout::ItemCompressed),
+    ] {
+        // and again, as if someone forgot it was already wrapped
+        let wrapped = wrap(&file)?;
+        for file in [wrap(&wrapped)?, wrapped] {
+            assert_eq!(items.to_vec(), read_all(&opts, file.clone())?);
+            let raw = opts.raw(io::Cursor::new(file))?;
+            assert_eq!(layout, raw.layout());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "gzip")]
+fn gzip_wrapped() -> anyhow::Result<()> {
+    use std::io::Write;
+    wrapped_round_trip(|file| {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(file)?;
+        gz.finish()
+    })
+}
+
+#[test]
+#[cfg(feature = "bzip2")]
+fn bzip2_wrapped() -> anyhow::Result<()> {
+    use std::io::Write;
+    wrapped_round_trip(|file| {
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        bz.write_all(file)?;
+        bz.finish()
+    })
+}
+
+#[test]
+#[cfg(not(feature = "gzip"))]
+fn gzip_unavailable() {
+    let gzipped = b"\x1f\x8b\x08\0\0\0\0\0";
+    assert!(matches!(
+        ExpandOptions::default().stream(io::Cursor::new(gzipped)),
+        Err(Error::CodecUnavailable("gzip"))
+    ));
+}
+
+#[test]
+fn nested_wrappers() -> anyhow::Result<()> {
+    let items = ["hello world", "bruises"];
+    let mut file = write_all(
+        CompressOptions::default().stream_compress(Vec::new())?,
+        &items,
+    )?;
+    let opts = ExpandOptions::default();
+    // the stream compression, and three more layers, are unwrapped
+    for _ in 0..3 {
+        file = zstd::encode_all(file.as_slice(), 1)?;
+    }
+    assert_eq!(items.to_vec(), read_all(&opts, file.clone())?);
+
+    for layers in [1, 1000] {
+        let mut nested = file.clone();
+        for _ in 0..layers {
+            nested = zstd::encode_all(nested.as_slice(), 1)?;
+        }
+        assert!(matches!(
+            opts.stream(io::Cursor::new(&nested)),
+            Err(Error::TooManyWrappers { max: 4 })
+        ));
+        assert!(matches!(
+            opts.raw(io::Cursor::new(&nested)),
+            Err(Error::TooManyWrappers { max: 4 })
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn archive_metadata() -> anyhow::Result<()> {
+    let opts = CompressOptions::default()
+        .with_metadata("producer", "round.rs")
+        .with_metadata("schema", "strings, v2")
+        .with_metadata("schema", "");
+    let expected = [
+        ("producer".to_string(), "round.rs".to_string()),
+        ("schema".to_string(), "strings, v2".to_string()),
+        ("schema".to_string(), String::new()),
+    ];
+    for file in [
+        write_all(opts.stream_compress(Vec::new())?, &["hello"])?,
+        write_all(opts.item_compress(Vec::new())?, &["hello"])?,
+    ] {
+        let mut archiv = ExpandOptions::default().stream(io::Cursor::new(&file))?;
+        assert_eq!(expected, archiv.archive_metadata());
+        let mut item = archiv.next_item()?.expect("an item");
+        let mut s = String::new();
+        item.read_to_string(&mut s)?;
+        assert_eq!("hello", s);
+
+        let raw = ExpandOptions::default().raw(io::Cursor::new(&file))?;
+        assert_eq!(expected, raw.archive_metadata());
+    }
+
+    let file = write_all(
+        CompressOptions::default().item_compress(Vec::new())?,
+        &["hello"],
+    )?;
+    let archiv = ExpandOptions::default().stream(io::Cursor::new(file))?;
+    assert!(archiv.archive_metadata().is_empty());
+    Ok(())
+}
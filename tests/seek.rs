@@ -0,0 +1,171 @@
+use std::io;
+use std::io::Read;
+
+use archiv::{Compress, CompressOptions, Expand, ExpandOptions, SeekTable};
+
+fn seekable(opts: CompressOptions) -> anyhow::Result<(Vec<String>, Vec<u64>, Vec<u8>)> {
+    let originals = (0..1000)
+        .map(|i| format!("item {i} {}", "abc".repeat(i % 20)))
+        .collect::<Vec<_>>();
+    let mut archiv = opts.stream_compress(Vec::new())?;
+    let mut offsets = Vec::with_capacity(originals.len());
+    for item in &originals {
+        offsets.push(archiv.write_item_vectored(&[item.as_bytes()])?);
+    }
+    Ok((originals, offsets, archiv.finish()?))
+}
+
+fn read_rest(archiv: &mut dyn Expand) -> anyhow::Result<Vec<String>> {
+    let mut items = Vec::new();
+    while let Some(mut v) = archiv.next_item()? {
+        let mut buf = String::new();
+        v.read_to_string(&mut buf)?;
+        items.push(buf);
+    }
+    Ok(items)
+}
+
+#[test]
+fn seek_by_items() -> anyhow::Result<()> {
+    let (originals, offsets, file) = seekable(
+        CompressOptions::default()
+            .with_frame_items(100)
+            .with_trailer(true),
+    )?;
+
+    // still a normal archive
+    let mut archiv = ExpandOptions::default().stream(io::Cursor::new(&file))?;
+    assert_eq!(originals, read_rest(archiv.as_mut())?);
+    let mut raw = ExpandOptions::default().raw(io::Cursor::new(&file))?;
+    while raw.next_raw(&mut Vec::new())?.is_some() {}
+    raw.check_eof()?;
+
+    let table = SeekTable::read_from(io::Cursor::new(&file))?;
+    // ten full frames, then one with just the footer
+    assert_eq!(11, table.frames().len());
+    assert_eq!(offsets[100], table.frames()[1].decompressed_offset);
+
+    for i in [0, 1, 99, 100, 537, 999] {
+        let mut archiv =
+            ExpandOptions::default().seek_stream(io::Cursor::new(&file), &table, offsets[i])?;
+        assert_eq!(originals[i..], read_rest(&mut archiv)?, "from item {i}");
+    }
+
+    let mut archiv = ExpandOptions::default().seek_stream(io::Cursor::new(&file), &table, 0)?;
+    assert_eq!(originals, read_rest(&mut archiv)?);
+    Ok(())
+}
+
+#[test]
+fn seek_by_bytes() -> anyhow::Result<()> {
+    let (originals, offsets, file) = seekable(
+        CompressOptions::default()
+            .with_frame_bytes(4096)
+            .with_workers(2)
+            .with_metadata("source", "seek_by_bytes"),
+    )?;
+    let table = SeekTable::read_from(io::Cursor::new(&file))?;
+    assert!(table.frames().len() > 5);
+    let (last, full) = table.frames().split_last().expect("frames present");
+    let footer = last.decompressed_offset + last.decompressed_size - 8;
+    for frame in full {
+        let end = frame.decompressed_offset + frame.decompressed_size;
+        assert!(frame.decompressed_size >= 4096);
+        assert!(offsets.contains(&end) || end == footer);
+    }
+
+    let mut archiv =
+        ExpandOptions::default().seek_stream(io::Cursor::new(&file), &table, offsets[420])?;
+    let metadata = [("source".to_string(), "seek_by_bytes".to_string())];
+    assert_eq!(metadata, archiv.archive_metadata());
+    assert_eq!(originals[420..], read_rest(&mut archiv)?);
+
+    // the header and table are read once, for any number of seeks
+    let mut seekable = ExpandOptions::default().seekable_stream(io::Cursor::new(&file))?;
+    assert_eq!(&table, seekable.table());
+    for (offset, i) in [(offsets[900], 900), (offsets[420], 420), (0, 0)] {
+        let mut archiv = seekable.seek(offset)?;
+        assert_eq!(metadata, archiv.archive_metadata());
+        assert_eq!(originals[i..], read_rest(&mut archiv)?, "from item {i}");
+    }
+
+    // a table isn't tied to a file, so the header is read from whichever it's used with
+    let mut damaged = file.clone();
+    damaged[..4].fill(0);
+    assert!(ExpandOptions::default()
+        .seek_stream(io::Cursor::new(&damaged), &table, offsets[900])
+        .is_err());
+    assert!(ExpandOptions::default()
+        .seekable_stream(io::Cursor::new(&damaged))
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn not_seekable() -> anyhow::Result<()> {
+    let (_, _, file) = seekable(CompressOptions::default())?;
+    assert!(SeekTable::read_from(io::Cursor::new(&file)).is_err());
+    Ok(())
+}
+
+#[test]
+fn oversized_frame_refused() -> anyhow::Result<()> {
+    let mut archiv = CompressOptions::default()
+        .with_frame_items(100)
+        .stream_compress(Vec::new())?;
+    archiv.write_item(b"before")?;
+    // four GiB, which the seek table can't record, without allocating it
+    let chunk = vec![0u8; 1024 * 1024];
+    let huge = vec![chunk.as_slice(); 4096];
+    assert!(matches!(
+        archiv.write_item_vectored(&huge),
+        Err(archiv::Error::LengthOverflow)
+    ));
+    archiv.write_item(b"after")?;
+    let file = archiv.finish()?;
+
+    let table = SeekTable::read_from(io::Cursor::new(&file))?;
+    let mut archiv = ExpandOptions::default().seek_stream(io::Cursor::new(&file), &table, 0)?;
+    assert_eq!(vec!["before", "after"], read_rest(&mut archiv)?);
+    Ok(())
+}
+
+#[test]
+fn seek_errors_located() -> anyhow::Result<()> {
+    let (_, offsets, file) = seekable(CompressOptions::default().with_frame_items(100))?;
+    let table = SeekTable::read_from(io::Cursor::new(&file))?;
+    let frames = table.frames();
+    // part of the way through the sixth frame, after the seek point
+    let cut = frames[5].compressed_offset + frames[5].compressed_size / 2;
+    let truncated = &file[..usize::try_from(cut)?];
+
+    let mut archiv =
+        ExpandOptions::default().seek_stream(io::Cursor::new(truncated), &table, offsets[150])?;
+    let error = loop {
+        match archiv.next_item() {
+            Ok(Some(mut item)) => {
+                if let Err(e) = io::copy(&mut item, &mut io::sink()) {
+                    break archiv::Error::from(e);
+                }
+            }
+            Ok(None) => panic!("truncated archive read"),
+            Err(e) => break e,
+        }
+    };
+    let at = match error {
+        archiv::Error::Io { source } => *source
+            .into_inner()
+            .expect("custom")
+            .downcast::<archiv::Error>()
+            .expect("archiv error"),
+        other => other,
+    };
+    let archiv::Error::At { index, offset, .. } = at else {
+        panic!("unlocated: {at:?}");
+    };
+    // the position is unknown after a seek, but the offset is in the file, after the seek point
+    assert_eq!(None, index);
+    assert!(offset > frames[1].compressed_offset, "{offset}");
+    assert!(offset <= cut, "{offset} > {cut}");
+    Ok(())
+}
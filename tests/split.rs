@@ -0,0 +1,264 @@
+#![cfg(feature = "bin")]
+
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use archiv::{Compress, CompressOptions, DecoderDictionary, ExpandOptions, Layout};
+
+fn run(args: &[&str]) -> anyhow::Result<()> {
+    let status = Command::new(env!("CARGO_BIN_EXE_archiv"))
+        .args(args)
+        .status()?;
+    anyhow::ensure!(status.success(), "archiv {args:?}: {status}");
+    Ok(())
+}
+
+fn path(p: &Path) -> &str {
+    p.to_str().expect("utf-8 temp path")
+}
+
+/// pack `count` items, as files, into an archiv
+fn pack(dir: &Path, name: &str, count: usize, extra: &[&str]) -> anyhow::Result<PathBuf> {
+    let inputs = dir.join(format!("{name}-inputs"));
+    fs::create_dir(&inputs)?;
+    let mut files = Vec::new();
+    for i in 0..count {
+        let file = inputs.join(format!("{i:03}"));
+        fs::write(&file, format!("{name} item {i} {}", "abc".repeat(i + 10)))?;
+        files.push(file);
+    }
+    let out = dir.join(format!("{name}.archiv"));
+    let mut args = vec!["pack", "-o", path(&out)];
+    args.extend_from_slice(extra);
+    args.extend(files.iter().map(|f| path(f)));
+    run(&args)?;
+    Ok(out)
+}
+
+fn read_items(file: &Path, opts: &ExpandOptions) -> anyhow::Result<(Layout, Vec<String>)> {
+    let layout = opts
+        .raw(io::BufReader::new(fs::File::open(file)?))?
+        .layout();
+    let mut archiv = opts.stream(io::BufReader::new(fs::File::open(file)?))?;
+    let mut items = Vec::new();
+    while let Some(mut item) = archiv.next_item()? {
+        let mut s = String::new();
+        item.read_to_string(&mut s)?;
+        items.push(s);
+    }
+    Ok((layout, items))
+}
+
+fn parts(prefix: &Path) -> Vec<PathBuf> {
+    (0..)
+        .map(|part| PathBuf::from(format!("{}.{part:04}.archiv", prefix.display())))
+        .take_while(|p| p.exists())
+        .collect()
+}
+
+#[test]
+fn split_merge_round_trip() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let opts = ExpandOptions::default();
+    for (name, kind, layout) in [
+        ("items", "--items", Layout::ItemCompressed),
+        ("stream", "--stream", Layout::StreamCompressed),
+    ] {
+        let input = pack(dir.path(), name, 10, &[kind])?;
+        let (_, originals) = read_items(&input, &opts)?;
+        assert_eq!(10, originals.len());
+
+        let prefix = dir.path().join(format!("{name}-part"));
+        run(&[
+            "split",
+            path(&input),
+            "--items",
+            "3",
+            "--prefix",
+            path(&prefix),
+        ])?;
+        let parts = parts(&prefix);
+        assert_eq!(4, parts.len());
+        let mut split = Vec::new();
+        for part in &parts {
+            let (part_layout, items) = read_items(part, &opts)?;
+            assert_eq!(layout, part_layout);
+            assert!(items.len() <= 3);
+            split.extend(items);
+        }
+        assert_eq!(originals, split);
+
+        let merged = dir.path().join(format!("{name}-merged.archiv"));
+        let mut args = vec!["merge", "-o", path(&merged)];
+        args.extend(parts.iter().map(|p| path(p)));
+        run(&args)?;
+        assert_eq!((layout, originals), read_items(&merged, &opts)?);
+    }
+    Ok(())
+}
+
+#[test]
+fn split_by_bytes() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let input = pack(dir.path(), "bytes", 20, &["--items"])?;
+    let prefix = dir.path().join("part");
+    run(&[
+        "split",
+        path(&input),
+        "--bytes",
+        "200",
+        "--prefix",
+        path(&prefix),
+    ])?;
+    let parts = parts(&prefix);
+    assert!(parts.len() > 2);
+
+    let opts = ExpandOptions::default();
+    let mut split = Vec::new();
+    for part in &parts {
+        let mut archiv = opts.raw(io::BufReader::new(fs::File::open(part)?))?;
+        let (mut items, mut bytes) = (0, 0);
+        while let Some(item) = archiv.next_raw(&mut Vec::new())? {
+            items += 1;
+            bytes += 8 + item.stored_len;
+        }
+        // only a single item may be over the limit on its own
+        assert!(bytes <= 200 || items == 1, "{part:?}: {bytes} bytes");
+        split.extend(read_items(part, &opts)?.1);
+    }
+    assert_eq!(read_items(&input, &opts)?.1, split);
+    Ok(())
+}
+
+#[test]
+fn merge_recompresses_other_dictionaries() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let samples = (0..200)
+        .map(|i| format!("dict item {i} {}", "abc".repeat(i % 20)).into_bytes())
+        .collect::<Vec<_>>();
+    let dict = zstd::dict::from_samples(&samples, 4096)?;
+    let dict_path = dir.path().join("dict");
+    fs::write(&dict_path, &dict)?;
+
+    let plain = pack(dir.path(), "plain", 5, &["--items"])?;
+    let with_dict = pack(
+        dir.path(),
+        "dict",
+        5,
+        &["--items", "--dict", path(&dict_path)],
+    )?;
+    let merged = dir.path().join("merged.archiv");
+    run(&[
+        "merge",
+        "--dict",
+        path(&dict_path),
+        "-o",
+        path(&merged),
+        path(&plain),
+        path(&with_dict),
+    ])?;
+
+    let decoder = DecoderDictionary::copy(&dict);
+    let opts = ExpandOptions::default().with_dict(&decoder);
+    let mut archiv = opts.raw(io::BufReader::new(fs::File::open(&merged)?))?;
+    let mut dict_ids = Vec::new();
+    while let Some(item) = archiv.next_raw(&mut Vec::new())? {
+        dict_ids.push(item.dict_id);
+    }
+    // every frame is readable with the one dictionary
+    assert_eq!(10, dict_ids.len());
+    assert!(
+        dict_ids.iter().all(|id| id.is_some() && *id == dict_ids[0]),
+        "{dict_ids:?}"
+    );
+
+    let mut originals = read_items(&plain, &ExpandOptions::default())?.1;
+    originals.extend(read_items(&with_dict, &opts)?.1);
+    assert_eq!(
+        (Layout::ItemCompressed, originals),
+        read_items(&merged, &opts)?
+    );
+    Ok(())
+}
+
+#[test]
+fn split_merge_plain() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut archiv = CompressOptions::default().plain(Vec::new())?;
+    let originals = (0..5)
+        .map(|i| format!("plain item {i}"))
+        .collect::<Vec<_>>();
+    for item in &originals {
+        archiv.write_item(item.as_bytes())?;
+    }
+    let input = dir.path().join("plain.archiv");
+    fs::write(&input, archiv.finish()?)?;
+
+    let opts = ExpandOptions::default();
+    run(&["split", path(&input), "--items", "2"])?;
+    let parts = parts(&dir.path().join("plain"));
+    assert_eq!(3, parts.len());
+    let mut split = Vec::new();
+    for part in &parts {
+        let (layout, items) = read_items(part, &opts)?;
+        assert_eq!(Layout::Plain, layout);
+        split.extend(items);
+    }
+    assert_eq!(originals, split);
+
+    let merged = dir.path().join("merged.archiv");
+    let mut args = vec!["merge", "-o", path(&merged)];
+    args.extend(parts.iter().map(|p| path(p)));
+    run(&args)?;
+    assert_eq!((Layout::Plain, originals), read_items(&merged, &opts)?);
+    Ok(())
+}
+
+#[test]
+fn merge_without_stored_items() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    // xorshift, which doesn't compress, so is stored
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let noise = (0..4096)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect::<Vec<u8>>();
+    let file = dir.path().join("noise");
+    fs::write(&file, &noise)?;
+
+    let stored = dir.path().join("stored.archiv");
+    run(&[
+        "pack",
+        "--items",
+        "--stored-items",
+        "-o",
+        path(&stored),
+        path(&file),
+    ])?;
+    let opts = ExpandOptions::default();
+    let mut archiv = opts.raw(io::BufReader::new(fs::File::open(&stored)?))?;
+    assert!(archiv.stored_items());
+    let item = archiv.next_raw(&mut Vec::new())?.expect("an item");
+    assert_eq!(noise.len() as u64 + 8, item.stored_len);
+
+    // the frame is compressed again, so the output can be read by older readers
+    let merged = dir.path().join("merged.archiv");
+    run(&["merge", "--items", "-o", path(&merged), path(&stored)])?;
+    assert_eq!(0, fs::read(&merged)?[6]);
+    let mut archiv = opts.stream(io::BufReader::new(fs::File::open(&merged)?))?;
+    let mut item = Vec::new();
+    archiv
+        .next_item()?
+        .expect("an item")
+        .read_to_end(&mut item)?;
+    assert_eq!(noise, item);
+    assert!(archiv.next_item()?.is_none());
+    Ok(())
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "bin")]
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use archiv::{Compress, CompressOptions, SeekTable};
+
+/// `archiv stats --json`, for a single file
+fn stats(file: &Path, args: &[&str]) -> anyhow::Result<serde_json::Value> {
+    let output = Command::new(env!("CARGO_BIN_EXE_archiv"))
+        .arg("stats")
+        .arg("--json")
+        .args(args)
+        .arg(file)
+        .output()?;
+    anyhow::ensure!(output.status.success(), "{output:?}");
+    let mut all: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    anyhow::ensure!(all.len() == 1, "{all:?}");
+    Ok(all.remove(0))
+}
+
+#[test]
+fn item_errors_counted() -> anyhow::Result<()> {
+    let mut archiv = CompressOptions::default().item_compress(Vec::new())?;
+    let mut offsets = Vec::new();
+    for i in 0..10 {
+        offsets.push(archiv.write_item(format!("item number {i}").as_bytes())?);
+    }
+    let mut file = archiv.finish()?;
+    // item 3's frame loses its magic, so it can't be decoded, but can be stepped over
+    let frame = usize::try_from(offsets[3])? + 8;
+    file[frame..frame + 4].fill(0);
+
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("item_errors_counted.archiv");
+    fs::write(&path, &file)?;
+
+    let report = stats(&path, &["--decompress"])?;
+    assert_eq!(10, report["items"]);
+    assert_eq!(true, report["footer"]["valid"]);
+    let errors = report["item_errors"].as_array().expect("errors");
+    assert_eq!(1, errors.len());
+    let error = errors[0].as_str().expect("error text");
+    assert!(
+        error.starts_with(&format!("item 3, at offset {}", offsets[3])),
+        "{error}"
+    );
+    assert_eq!(1, report["unknown_sizes"]);
+
+    // a broken length can't be stepped over, so the footer isn't reached
+    let mut truncated = file.clone();
+    let marker = usize::try_from(offsets[5])?;
+    truncated[marker..marker + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    fs::write(&path, &truncated)?;
+    let report = stats(&path, &[])?;
+    assert_eq!(5, report["items"]);
+    assert_eq!(false, report["footer"]["valid"]);
+    let errors = report["item_errors"].as_array().expect("errors");
+    assert_eq!(1, errors.len());
+    Ok(())
+}
+
+#[test]
+fn stream_trailer_without_decompressing() -> anyhow::Result<()> {
+    let mut archiv = CompressOptions::default()
+        .with_trailer(true)
+        .with_frame_items(2)
+        .stream_compress(Vec::new())?;
+    for i in 0..10 {
+        archiv.write_item(format!("item number {i}").as_bytes())?;
+    }
+    let mut file = archiv.finish()?;
+    // the third frame loses its magic, so nothing after it can be decompressed
+    let table = SeekTable::read_from(io::Cursor::new(&file))?;
+    let frame = usize::try_from(table.frames()[2].compressed_offset)?;
+    file[frame..frame + 4].fill(0);
+
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("stream_trailer.archiv");
+    fs::write(&path, &file)?;
+
+    let report = stats(&path, &[])?;
+    assert_eq!("stream-compressed", report["kind"]);
+    assert_eq!(10, report["items"]);
+    assert_eq!(10 * 13, report["uncompressed_bytes"]);
+    assert!(report["trailer"].is_object(), "{report}");
+
+    let report = stats(&path, &["--decompress"])?;
+    assert_eq!(4, report["items"]);
+    assert_eq!(false, report["footer"]["valid"]);
+    Ok(())
+}
@@ -0,0 +1,86 @@
+#![cfg(any(feature = "json", feature = "cbor", feature = "postcard"))]
+
+use std::io;
+
+use archiv::{Compress, CompressOptions, Encoding, ExpandOptions, TypedCompress, TypedExpand};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Record {
+    id: u64,
+    name: String,
+    tags: Vec<String>,
+}
+
+fn records() -> Vec<Record> {
+    (0..100)
+        .map(|id| Record {
+            id,
+            name: format!("record {id}"),
+            tags: (0..id % 4).map(|t| format!("tag{t}")).collect(),
+        })
+        .collect()
+}
+
+fn write_typed<E: Encoding>(
+    archiv: impl Compress<Vec<u8>>,
+    encoding: E,
+    records: &[Record],
+) -> anyhow::Result<Vec<u8>> {
+    let mut archiv = TypedCompress::new(archiv, encoding);
+    for record in records {
+        archiv.write(record)?;
+    }
+    Ok(archiv.finish()?)
+}
+
+fn typed_round_trip(encoding: impl Encoding + Copy) -> anyhow::Result<()> {
+    let originals = records();
+    let opts = CompressOptions::default().with_metadata("schema", "Record");
+    for file in [
+        write_typed(opts.stream_compress(Vec::new())?, encoding, &originals)?,
+        write_typed(opts.item_compress(Vec::new())?, encoding, &originals)?,
+    ] {
+        let archiv = ExpandOptions::default().stream(io::Cursor::new(file))?;
+        let archiv = TypedExpand::new(archiv, encoding);
+        assert_eq!("Record", archiv.archive_metadata()[0].1);
+        let items = archiv.collect::<Result<Vec<Record>, _>>()?;
+        assert_eq!(originals, items);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn json_round_trip() -> anyhow::Result<()> {
+    typed_round_trip(archiv::Json)
+}
+
+#[test]
+#[cfg(feature = "cbor")]
+fn cbor_round_trip() -> anyhow::Result<()> {
+    typed_round_trip(archiv::Cbor)
+}
+
+#[test]
+#[cfg(feature = "postcard")]
+fn postcard_round_trip() -> anyhow::Result<()> {
+    typed_round_trip(archiv::Postcard)
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn decode_errors() -> anyhow::Result<()> {
+    let mut archiv = CompressOptions::default().item_compress(Vec::new())?;
+    archiv.write_item(b"{\"id\": 1}")?;
+    let file = archiv.finish()?;
+
+    let archiv = ExpandOptions::default().stream(io::Cursor::new(file))?;
+    let mut archiv = TypedExpand::<Record, _, _>::new(archiv, archiv::Json);
+    assert!(matches!(
+        archiv.next_value(),
+        Err(archiv::Error::Encoding { .. })
+    ));
+    assert!(archiv.next_value()?.is_none());
+    Ok(())
+}
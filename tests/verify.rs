@@ -0,0 +1,302 @@
+use std::io;
+use std::io::Read;
+
+use archiv::{Compress, CompressOptions, Error, ExpandOptions, ItemCodec, Layout, VerifyReport};
+
+fn items_archive() -> anyhow::Result<(Vec<u8>, Vec<u64>)> {
+    let mut archiv = CompressOptions::default().item_compress(Vec::new())?;
+    let mut offsets = Vec::new();
+    for i in 0..10 {
+        offsets.push(archiv.write_item(format!("item number {i}").as_bytes())?);
+    }
+    Ok((archiv.finish()?, offsets))
+}
+
+fn verify(file: &[u8]) -> VerifyReport {
+    ExpandOptions::default().verify(io::Cursor::new(file))
+}
+
+#[test]
+fn verify_ok() -> anyhow::Result<()> {
+    let (file, _) = items_archive()?;
+    let report = verify(&file);
+    assert!(report.is_ok(), "{report:?}");
+    assert_eq!(Some(Layout::ItemCompressed), report.layout);
+    assert_eq!(10, report.items);
+
+    let mut archiv = CompressOptions::default().stream_compress(Vec::new())?;
+    archiv.write_item(b"hello")?;
+    let report = verify(&archiv.finish()?);
+    assert!(report.is_ok(), "{report:?}");
+    assert_eq!(Some(Layout::StreamCompressed), report.layout);
+    assert_eq!(1, report.items);
+    Ok(())
+}
+
+#[test]
+fn verify_corrupt_frame() -> anyhow::Result<()> {
+    let (mut file, offsets) = items_archive()?;
+    // the declared content size, after the length, zstd magic and frame header descriptor
+    file[usize::try_from(offsets[3])? + 8 + 4 + 1] ^= 0x01;
+    let report = verify(&file);
+    let failure = report.failure.expect("corrupt");
+    assert_eq!(3, failure.index);
+    assert_eq!(offsets[3], failure.offset);
+    assert_eq!(3, report.items);
+    Ok(())
+}
+
+#[test]
+fn verify_header_footer() -> anyhow::Result<()> {
+    let (file, offsets) = items_archive()?;
+
+    let report = verify(&file[1..]);
+    assert!(report.layout.is_none());
+    assert!(matches!(
+        report.failure.expect("no header").error,
+        Error::MagicMissing { offset: 0, .. }
+    ));
+
+    let truncated = &file[..file.len() - 4];
+    let failure = verify(truncated).failure.expect("truncated");
+    assert_eq!(10, failure.index);
+    assert!(matches!(failure.error, Error::Io { .. }));
+
+    let truncated = &file[..usize::try_from(offsets[6])? + 3];
+    let failure = verify(truncated).failure.expect("truncated");
+    assert_eq!((6, offsets[6]), (failure.index, failure.offset));
+
+    let mut trailing = file.clone();
+    trailing.extend_from_slice(b"hello");
+    let failure = verify(&trailing).failure.expect("trailing");
+    assert_eq!(file.len() as u64, failure.offset);
+    assert!(matches!(failure.error, Error::TrailingData));
+    Ok(())
+}
+
+#[test]
+fn magic_diagnostics() -> anyhow::Result<()> {
+    let open = |file: Vec<u8>| ExpandOptions::default().stream(io::Cursor::new(file)).err();
+
+    let Some(Error::MagicMissing {
+        offset,
+        found,
+        guess,
+    }) = open(b"PK\x03\x04hello".to_vec())
+    else {
+        panic!("zip accepted");
+    };
+    assert_eq!(0, offset);
+    assert_eq!(b"PK\x03\x04hello", found.as_slice());
+    assert_eq!(Some("a zip file"), guess.as_deref());
+
+    let zstd = zstd::encode_all(&b"hello world"[..], 0)?;
+    let Some(Error::MagicMissing { found, guess, .. }) = open(zstd) else {
+        panic!("plain zstd accepted");
+    };
+    assert_eq!(b"hello world", found.as_slice());
+    assert_eq!(Some("zstd, but not an archiv"), guess.as_deref());
+
+    let Some(Error::MagicUnrecognised { what, value, .. }) = open(b"\x29\xb6arc\0\0\x09".to_vec())
+    else {
+        panic!("unknown kind accepted");
+    };
+    assert_eq!(("kind", 9), (what, value));
+    Ok(())
+}
+
+#[test]
+fn expand_errors_located() -> anyhow::Result<()> {
+    let (file, offsets) = items_archive()?;
+    let truncated = &file[..usize::try_from(offsets[6])? + 3];
+    let mut archiv = ExpandOptions::default().stream(io::Cursor::new(truncated))?;
+    for _ in 0..6 {
+        archiv.next_item()?.expect("an item");
+    }
+    let Err(Error::At {
+        index,
+        offset,
+        source,
+    }) = archiv.next_item()
+    else {
+        panic!("truncated item read");
+    };
+    assert_eq!((Some(6), offsets[6]), (index, offset));
+    assert!(matches!(*source, Error::Io { .. }));
+
+    let mut archiv = CompressOptions::default().stream_compress(Vec::new())?;
+    for i in 0..20_000 {
+        archiv.write_item(format!("item number {i}").as_bytes())?;
+    }
+    let file = archiv.finish()?;
+    let truncated = &file[..file.len() / 2];
+    let mut archiv = ExpandOptions::default().stream(io::Cursor::new(truncated))?;
+    let error = loop {
+        match archiv.next_item() {
+            Ok(Some(mut item)) => {
+                if let Err(e) = io::copy(&mut item, &mut io::sink()) {
+                    break Error::from(e);
+                }
+            }
+            Ok(None) => panic!("truncated archive read"),
+            Err(e) => break e,
+        }
+    };
+    // either the marker, or the item itself, knows where it was
+    let at = match error {
+        Error::Io { source } => *source
+            .into_inner()
+            .expect("custom")
+            .downcast::<Error>()
+            .expect("archiv error"),
+        other => other,
+    };
+    let Error::At { index, offset, .. } = at else {
+        panic!("unlocated: {at:?}");
+    };
+    assert!(index.is_some_and(|index| index < 20_000));
+    assert!(offset <= truncated.len() as u64);
+    Ok(())
+}
+
+#[test]
+fn extended_header() -> anyhow::Result<()> {
+    let (file, offsets) = items_archive()?;
+    let items = &file[8..];
+    // version 1: no flags, and an extension no reader understands
+    let mut extended = b"\x29\xb6arc\0\x01\x01".to_vec();
+    extended.extend_from_slice(&0u32.to_le_bytes());
+    extended.extend_from_slice(&11u32.to_le_bytes());
+    extended.extend_from_slice(&0x7777u16.to_le_bytes());
+    extended.extend_from_slice(&5u32.to_le_bytes());
+    extended.extend_from_slice(b"hello");
+    let shift = extended.len() as u64 - 8;
+    extended.extend_from_slice(items);
+
+    let report = verify(&extended);
+    assert!(report.is_ok(), "{report:?}");
+    assert_eq!(10, report.items);
+
+    let mut archiv = ExpandOptions::default().raw_seekable(io::Cursor::new(&extended))?;
+    let mut raw = Vec::new();
+    let first = archiv.next_raw(&mut raw)?.expect("an item");
+    assert_eq!(offsets[0] + shift, first.offset);
+    let mut item =
+        ExpandOptions::default().item_at(io::Cursor::new(&extended), offsets[4] + shift)?;
+    let mut s = String::new();
+    item.read_to_string(&mut s)?;
+    assert_eq!("item number 4", s);
+    drop(item);
+
+    // a flag this reader doesn't know about
+    let mut flagged = extended.clone();
+    flagged[8] = 0x04;
+    let Some(Error::MagicUnrecognised { what, value, .. }) =
+        verify(&flagged).failure.map(|f| f.error)
+    else {
+        panic!("unknown flag accepted");
+    };
+    assert_eq!(("flag", 2), (what, value));
+
+    // one it doesn't know about, but which it's allowed to ignore
+    let mut ignorable = flagged.clone();
+    ignorable[8] = 0;
+    ignorable[10] = 0x10;
+    let report = verify(&ignorable);
+    assert!(report.is_ok(), "{report:?}");
+    assert_eq!(10, report.items);
+
+    let mut future = extended;
+    future[6] = 0x09;
+    let Some(Error::MagicUnrecognised { what, value, .. }) =
+        verify(&future).failure.map(|f| f.error)
+    else {
+        panic!("unknown version accepted");
+    };
+    assert_eq!(("version", 9), (what, value));
+    Ok(())
+}
+
+#[test]
+fn trailer() -> anyhow::Result<()> {
+    let opts = CompressOptions::default()
+        .with_trailer(true)
+        .with_stored_items(true);
+    let mut archiv = opts.item_compress(Vec::new())?;
+    for i in 0..10 {
+        archiv.write_item(format!("item number {i}").as_bytes())?;
+    }
+    archiv.write_item_with(b"stored", ItemCodec::Store)?;
+    let file = archiv.finish()?;
+
+    let trailer = ExpandOptions::default()
+        .trailer(io::Cursor::new(&file))?
+        .expect("trailer");
+    assert_eq!(11, trailer.items);
+    assert_eq!(
+        10 * 13 + 6,
+        trailer.uncompressed_bytes.expect("sizes known")
+    );
+    let report = verify(&file);
+    assert!(report.is_ok(), "{report:?}");
+    assert_eq!(11, report.items);
+
+    let truncated = &file[..file.len() - 1];
+    assert!(matches!(
+        ExpandOptions::default().trailer(io::Cursor::new(truncated)),
+        Err(Error::TrailerMissing { .. })
+    ));
+    let failure = verify(truncated).failure.expect("truncated");
+    assert!(matches!(failure.error, Error::TrailerMissing { .. }));
+
+    // the stored item is just before the footer and trailer, and isn't checked by a codec
+    let mut corrupt = file.clone();
+    corrupt[file.len() - 8 - 40 - 1] ^= 0x01;
+    let failure = verify(&corrupt).failure.expect("corrupt");
+    assert!(matches!(
+        failure.error,
+        Error::TrailerMismatch {
+            what: "checksum",
+            ..
+        }
+    ));
+
+    // stream-compressed archives have it after the compressed stream, or before a seek table
+    for seekable in [false, true] {
+        let mut opts = CompressOptions::default().with_trailer(true);
+        if seekable {
+            opts = opts.with_frame_items(1);
+        }
+        let mut archiv = opts.stream_compress(Vec::new())?;
+        archiv.write_item(b"hello")?;
+        archiv.write_item(b"world")?;
+        let file = archiv.finish()?;
+        let trailer = ExpandOptions::default()
+            .trailer(io::Cursor::new(&file))?
+            .expect("trailer");
+        assert_eq!((2, Some(10)), (trailer.items, trailer.uncompressed_bytes));
+        let report = verify(&file);
+        assert!(report.is_ok(), "{report:?}");
+        assert!(matches!(
+            ExpandOptions::default().trailer(io::Cursor::new(&file[..file.len() - 1])),
+            Err(Error::TrailerMissing { .. })
+        ));
+    }
+
+    // the trailer's flag is one readers which don't know about it can ignore
+    let mut archiv = CompressOptions::default()
+        .with_trailer(true)
+        .item_compress(Vec::new())?;
+    archiv.write_item(b"hello")?;
+    let file = archiv.finish()?;
+    assert_eq!([0, 0], file[8..10]);
+    assert_ne!([0, 0], file[10..12]);
+
+    // older archives don't have one
+    let (file, _) = items_archive()?;
+    assert_eq!(
+        None,
+        ExpandOptions::default().trailer(io::Cursor::new(&file))?
+    );
+    Ok(())
+}